@@ -0,0 +1,74 @@
+//! Compare the throughput of the thread safe caches with the number of threads
+//!
+//! Run with `cargo bench --bench cache_throughput`
+
+use std::time::{Duration, Instant};
+
+use network_power_4::{
+    End, Game, Player,
+    caches::{KnowledgeCache, KnowledgeCacheMultiThread, KnowledgeCacheSharded},
+};
+use rand::{SeedableRng, rngs::StdRng, seq::IndexedRandom};
+
+const THREADS: [usize; 5] = [1, 2, 4, 8, 16];
+const POSITIONS: usize = 100_000;
+const OPERATIONS: usize = 1_000_000;
+/// One `remember` every `LOOKUPS` lookups, like a search finding few proven endings
+const LOOKUPS: usize = 4;
+
+/// Positions reached by random games
+fn positions() -> Vec<Game> {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut games = Vec::with_capacity(POSITIONS);
+    while games.len() < POSITIONS {
+        let mut game = Game::default();
+        loop {
+            let moves = game.board().legal_moves(game.next_to_play());
+            let &(c, _, end) = moves.choose(&mut rng).unwrap();
+            game.play(c).unwrap();
+            games.push(game.clone());
+            if end.is_some() || games.len() == POSITIONS {
+                break;
+            }
+        }
+    }
+    games
+}
+
+/// Operations per second of `threads` threads sharing `cache`
+fn throughput<C: KnowledgeCache + Sync>(cache: &C, games: &[Game], threads: usize) -> f64 {
+    let per_thread = OPERATIONS / threads;
+    let start = Instant::now();
+    std::thread::scope(|s| {
+        for t in 0..threads {
+            s.spawn(move || {
+                for i in 0..per_thread {
+                    let board = games[(i * 7919 + t * 104_729) % games.len()].board();
+                    if i % (LOOKUPS + 1) == 0 {
+                        cache.remember(board, Player::FIRST, 3, End::Stall);
+                    } else {
+                        std::hint::black_box(cache.lookup(board, Player::FIRST));
+                    }
+                }
+            });
+        }
+    });
+    let elapsed: Duration = start.elapsed();
+    (per_thread * threads) as f64 / elapsed.as_secs_f64()
+}
+
+fn main() {
+    let games = positions();
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    println!("{cores} cores, {OPERATIONS} operations");
+    println!("threads | single lock (Mops/s) | sharded (Mops/s)");
+    for threads in THREADS {
+        let single = throughput(&KnowledgeCacheMultiThread::default(), &games, threads);
+        let sharded = throughput(&KnowledgeCacheSharded::default(), &games, threads);
+        println!(
+            "{threads:>7} | {:>20.2} | {:>16.2}",
+            single / 1e6,
+            sharded / 1e6
+        );
+    }
+}
@@ -0,0 +1,40 @@
+//! Compare the parallel searches on the same positions
+//!
+//! Run with `cargo bench --bench parallel_search`
+
+use std::time::{Duration, Instant};
+
+use network_power_4::{
+    Game, SyncEvaluator,
+    evaluators::{LazySmp, ParallelMinMax},
+};
+
+const DEPTHS: [usize; 4] = [7, 8, 9, 10];
+const OPENING: [usize; 4] = [4, 3, 4, 5];
+
+fn time<E: SyncEvaluator>(evaluator: E, game: &Game) -> Duration {
+    let start = Instant::now();
+    evaluator.evaluate_game(game);
+    start.elapsed()
+}
+
+fn main() {
+    let mut game = Game::default();
+    for c in OPENING {
+        game.play(c).unwrap();
+    }
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    println!("{threads} threads");
+    println!("depth | split (ms) | lazy smp (ms) | sequential (ms)");
+    for depth in DEPTHS {
+        let split = time(ParallelMinMax::new(depth), &game);
+        let smp = time(LazySmp::new(depth), &game);
+        let sequential = time(LazySmp::new(depth).with_helpers(0), &game);
+        println!(
+            "{depth:>5} | {:>10} | {:>13} | {:>15}",
+            split.as_millis(),
+            smp.as_millis(),
+            sequential.as_millis()
+        );
+    }
+}
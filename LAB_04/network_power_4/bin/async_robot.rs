@@ -1,13 +1,20 @@
 use std::{
     io::Write,
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
 use clap::Parser;
-use log::{error, info, warn};
+use futures::{StreamExt, future::ready};
+use log::{debug, error, info, warn};
 use network_power_4::{
-    AsyncEvaluator, RemoteGame, Roles,
-    evaluators::{BlockingTaskWrapper, MinMaxPolicy},
+    AIType, AsyncEvaluator, RemoteGame, Roles, best_in_time,
+    evaluators::{Portfolio, RandomOpening, RandomPolicy},
+    heuristic::{NTupleNetwork, NaiveHeuristic},
+    registry::{Context, Registry, SharedHeuristic, Spec},
+    thread_pool::ThreadPoolBuilder,
 };
 use tokio::runtime;
 
@@ -21,8 +28,17 @@ struct Cli {
     /// Network adress
     remote_addr: String,
 
+    #[arg(required_unless_present_any = ["evaluator", "ai"])]
     /// Depth of the search
-    depth: usize,
+    depth: Option<usize>,
+
+    #[clap(long, conflicts_with_all = ["depth", "ai", "native"])]
+    /// Evaluator to play with, such as `async_minmax:depth=6,tasks=4` (see the `registry` module)
+    evaluator: Option<Spec>,
+
+    #[clap(long, value_enum, conflicts_with_all = ["depth", "native"])]
+    /// A preset evaluator
+    ai: Option<AIType>,
 
     #[clap(long, short, action)]
     /// Render the game
@@ -31,6 +47,99 @@ struct Cli {
     #[clap(long, short, action)]
     /// Ping to show that the program is alive
     alive: bool,
+
+    #[clap(long)]
+    /// Seed of every random choice, drawn at random (and logged) if absent
+    seed: Option<u64>,
+
+    #[clap(long, default_value_t = 0)]
+    /// Number of plies played at random at the start of the game
+    opening: usize,
+
+    #[clap(long)]
+    /// N-tuple network (see the `train` binary) used instead of the naive evaluation
+    heuristic: Option<PathBuf>,
+
+    #[clap(long)]
+    /// Time budget of a move in milliseconds, a random play is made if the search is not over
+    deadline: Option<u64>,
+
+    #[clap(long)]
+    /// Number of threads running the search, all the cores of the machine by default
+    threads: Option<NonZeroUsize>,
+
+    #[clap(long, action)]
+    /// Split the search into futures polled by the runtime, only its leaves running on the thread pool
+    native: bool,
+}
+
+/// The evaluator asked on the command line, the search flags being shorthands for a spec
+fn spec(args: &Cli) -> Spec {
+    if let Some(spec) = &args.evaluator {
+        return spec.clone();
+    }
+    if let Some(ai) = args.ai {
+        return ai.spec();
+    }
+    let depth = args
+        .depth
+        .expect("A depth is required without an evaluator");
+    let spec = match args.threads {
+        Some(threads) if args.native => format!("async_minmax:depth={depth},tasks={threads}"),
+        _ if args.native => format!("async_minmax:depth={depth}"),
+        _ => format!("minmax:depth={depth}"),
+    };
+    spec.parse().expect("The flags make a valid spec")
+}
+
+/// Play the game until its end with `evaluator`, committing to its best result within `budget`
+async fn play<E: AsyncEvaluator>(
+    mut game: RemoteGame,
+    evaluator: E,
+    render: bool,
+    budget: Option<Duration>,
+) {
+    let mut buff = std::io::BufWriter::new(std::io::stdout());
+
+    loop {
+        if render {
+            game.render(&mut buff);
+            buff.flush().unwrap();
+        }
+        info!("Thinking...");
+        let start = Instant::now();
+        let current = game.game();
+        let results = evaluator
+            .evaluate_game_stream(&current)
+            .inspect(|(p, e)| debug!("Current best : {p:?}, {e:?}"));
+        let best = match budget {
+            Some(budget) => best_in_time(results, budget).await,
+            None => results.fold(None, |_, r| ready(Some(r))).await,
+        };
+        let (p, e) = best.expect("The evaluator gives a result");
+        let end = Instant::now();
+        info!("Think for {:} ms", (end - start).as_millis());
+        info!("Playing {p:?}");
+        info!("Estimation : {e:?}");
+        let e = game.play(p.column()).await;
+        match e {
+            Ok(v) => match v {
+                Some(e) => {
+                    if render {
+                        game.render(&mut buff);
+                        buff.flush().unwrap();
+                    };
+                    info!("{e:?}");
+                    break;
+                }
+                None => continue,
+            },
+            Err(e) => {
+                error!("{e:?}");
+                panic!();
+            }
+        }
+    }
 }
 
 fn main() {
@@ -52,46 +161,59 @@ fn main() {
             });
         }
 
-        let mut game = match args.role {
-            Roles::Client => RemoteGame::new_client(args.remote_addr).await,
-            Roles::Host => RemoteGame::new_server(args.remote_addr).await,
+        let game = match args.role {
+            Roles::Client => RemoteGame::new_client(args.remote_addr.clone()).await,
+            Roles::Host => RemoteGame::new_server(args.remote_addr.clone()).await,
         };
         info!("Player connected!");
 
-        let evaluator = BlockingTaskWrapper::from(MinMaxPolicy::new(args.depth));
+        let seed = args.seed.unwrap_or_else(rand::random);
+        info!("Seed : {seed}");
+
+        let heuristic: SharedHeuristic = match &args.heuristic {
+            Some(path) => Arc::new(NTupleNetwork::load(path).expect("Can't load the heuristic")),
+            None => Arc::new(NaiveHeuristic),
+        };
 
-        let mut buff = std::io::BufWriter::new(std::io::stdout());
+        let mut pool = ThreadPoolBuilder::new().name("search");
+        if let Some(threads) = args.threads {
+            pool = pool.threads(threads.get());
+        }
+        let pool = Arc::new(pool.build().expect("Failed to spawn the search threads"));
+        info!("Searching on {} threads", pool.threads());
 
-        loop {
-            if args.render {
-                game.render(&mut buff);
-                buff.flush().unwrap();
+        let spec = spec(&args);
+        let search_pool = pool.clone();
+        let context = Context {
+            heuristic,
+            seed,
+            pool: Some(pool.clone()),
+        };
+        let registry = Registry::default();
+        info!("Evaluator : {spec}");
+        let deadline = args.deadline.map(Duration::from_millis);
+        match deadline {
+            Some(deadline) if registry.has_sync(spec.name()) => {
+                // The members of the portfolio are raced on the pool
+                let policy = registry
+                    .build_sync(&spec, &context)
+                    .unwrap_or_else(|e| panic!("Can't build the evaluator {spec} : {e}"));
+                let portfolio = Portfolio::new(deadline)
+                    .with_member(policy.evaluator)
+                    .with_member(RandomPolicy::from_seed(seed))
+                    .with_pool(pool);
+                let evaluator = RandomOpening::new(portfolio, args.opening, seed);
+                play(game, evaluator, args.render, None).await;
             }
-            info!("Thinking...");
-            let start = Instant::now();
-            let (p, e) = evaluator.evaluate_game(&game.game()).await;
-            let end = Instant::now();
-            info!("Think for {:} ms", (end - start).as_millis());
-            info!("Playing {p:?}");
-            info!("Estimation : {e:?}");
-            let e = game.play(p.column()).await;
-            match e {
-                Ok(v) => match v {
-                    Some(e) => {
-                        if args.render {
-                            game.render(&mut buff);
-                            buff.flush().unwrap();
-                        };
-                        info!("{e:?}");
-                        break;
-                    }
-                    None => continue,
-                },
-                Err(e) => {
-                    error!("{e:?}");
-                    panic!();
-                }
+            // The asynchronous evaluators stream their results, the last one in time is played
+            _ => {
+                let search = registry
+                    .build_async(&spec, &context)
+                    .unwrap_or_else(|e| panic!("Can't build the evaluator {spec} : {e}"));
+                let evaluator = RandomOpening::new(search.evaluator, args.opening, seed);
+                play(game, evaluator, args.render, deadline).await;
             }
         }
+        info!("Search pool : {}", search_pool.metrics());
     });
 }
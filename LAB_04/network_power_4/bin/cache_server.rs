@@ -0,0 +1,89 @@
+use std::{
+    net::TcpListener,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use log::{error, info, warn};
+use network_power_4::caches::{
+    KnowledgeCache, KnowledgeCacheBounded, KnowledgeCacheSharded, serve_client,
+};
+
+#[derive(clap::Parser)]
+#[command(version, about)]
+/// A cache server sharing the proven endings between several robots
+struct Cli {
+    /// Network adress
+    addr: String,
+
+    #[clap(long)]
+    /// Bound the memory of the cache to this number of MiB, evicting the least recently used entries
+    cache_size: Option<usize>,
+
+    #[clap(long)]
+    /// Load the cache from this file if it exists, and save it there regularly
+    cache_file: Option<PathBuf>,
+
+    #[clap(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+    /// Seconds between two saves of the cache file, at least one
+    save_interval: u64,
+}
+
+/// Serve the clients, each on its own thread, saving the cache regularly
+fn serve<C: KnowledgeCache + Clone + Send + 'static>(args: Cli, cache: C) {
+    if let Some(path) = &args.cache_file
+        && path.exists()
+    {
+        match cache.load(path) {
+            Ok(n) => info!("Loaded {n} entries from {}", path.display()),
+            Err(e) => warn!("Can't load the cache file : {e}"),
+        }
+    }
+
+    let listener = TcpListener::bind(&args.addr).expect("Failed to bind the TCP listener");
+    info!("Listening on {}", args.addr);
+    {
+        let cache = cache.clone();
+        std::thread::spawn(move || {
+            let interval = Duration::from_secs(args.save_interval);
+            let mut next = Instant::now() + interval;
+            loop {
+                std::thread::sleep(next.saturating_duration_since(Instant::now()));
+                next += interval;
+                info!("Cache : {} entries, {}", cache.len(), cache.stats());
+                if let Some(path) = &args.cache_file
+                    && let Err(e) = cache.save(path)
+                {
+                    error!("Can't save the cache file : {e}");
+                }
+            }
+        });
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("Failed to accept a connection : {e:?}");
+                continue;
+            }
+        };
+        let peer = stream.peer_addr().ok();
+        info!("Client connected : {peer:?}");
+        let mut cache = cache.clone();
+        std::thread::spawn(move || match serve_client(stream, &mut cache) {
+            Ok(()) => info!("Client disconnected : {peer:?}"),
+            Err(e) => warn!("Client {peer:?} dropped : {e:?}"),
+        });
+    }
+}
+
+fn main() {
+    colog::init();
+    let args = Cli::parse();
+    match args.cache_size {
+        Some(size) => serve(args, KnowledgeCacheBounded::with_memory(size << 20)),
+        None => serve(args, KnowledgeCacheSharded::default()),
+    }
+}
@@ -1,14 +1,60 @@
 use std::io::{BufWriter, stdout};
 
-use network_power_4::{Game, get_user_commande};
+use clap::Parser;
+use network_power_4::{
+    AIType, AsyncEvaluator, Game, Player,
+    executor::block_on,
+    get_user_commande,
+    registry::{Context, Registry, Spec},
+};
+
+#[derive(clap::Parser)]
+#[command(version, about)]
+struct Cli {
+    #[clap(long)]
+    /// Depth of a robot playing second, run by the built-in executor; two humans play if absent
+    robot: Option<usize>,
+
+    #[clap(long, conflicts_with_all = ["robot", "ai"])]
+    /// Evaluator of the robot, such as `minmax:depth=6,cache=multi` (see the `registry` module)
+    evaluator: Option<Spec>,
+
+    #[clap(long, value_enum, conflicts_with = "robot")]
+    /// A preset evaluator for the robot
+    ai: Option<AIType>,
+}
 
 fn main() {
+    let args = Cli::parse();
+    let spec = match (args.evaluator, args.ai, args.robot) {
+        (Some(spec), _, _) => Some(spec),
+        (None, Some(ai), _) => Some(ai.spec()),
+        (None, None, Some(depth)) => Some(
+            format!("minmax:depth={depth}")
+                .parse()
+                .expect("The depth makes a valid spec"),
+        ),
+        (None, None, None) => None,
+    };
+    // Every evaluator is driven by the built-in executor, the synchronous ones run on the shared pool
+    let robot = spec.map(|spec| {
+        Registry::default()
+            .build_async(&spec, &Context::default())
+            .unwrap_or_else(|e| panic!("Can't build the evaluator {spec} : {e}"))
+            .evaluator
+    });
+
     let mut game = Game::default();
     let out = stdout();
     let mut out = BufWriter::new(out);
     loop {
         game.render(&mut out);
-        let column = get_user_commande();
+        let column = match &robot {
+            Some(robot) if game.next_to_play() == Player::SECOND => {
+                block_on(robot.evaluate_game(&game)).0.column()
+            }
+            _ => get_user_commande(),
+        };
         let res = game.play(column);
         match res {
             Ok(e) => {
@@ -1,14 +1,18 @@
 use std::{
     io::Write,
+    path::PathBuf,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
 use clap::Parser;
 use log::{error, info, warn};
 use network_power_4::{
-    RemoteGame, Roles, SyncEvaluator,
-    caches::{KnowledgeCacheMultiThread, KnowledgeCacheSingleThread},
-    evaluators::{MinMaxPolicy, MinMaxPolicyCached, ThreadedPolicy},
+    AIType, RemoteGame, Roles, SyncEvaluator,
+    caches::KnowledgeCache,
+    evaluators::RandomOpening,
+    heuristic::{NTupleNetwork, NaiveHeuristic},
+    registry::{Context, Registry, SharedHeuristic, Spec},
 };
 use tokio::runtime;
 
@@ -22,8 +26,17 @@ struct Cli {
     /// Network adress
     remote_addr: String,
 
+    #[arg(required_unless_present_any = ["evaluator", "ai"])]
     /// Depth of the search
-    depth: usize,
+    depth: Option<usize>,
+
+    #[clap(long, conflicts_with_all = ["depth", "ai", "thread", "cache", "cache_size", "cache_server"])]
+    /// Evaluator to play with, such as `minmax:depth=6,cache=multi` (see the `registry` module)
+    evaluator: Option<Spec>,
+
+    #[clap(long, value_enum, conflicts_with_all = ["depth", "thread", "cache", "cache_size", "cache_server"])]
+    /// A preset evaluator
+    ai: Option<AIType>,
 
     #[clap(long, short, action)]
     /// Render the game
@@ -34,12 +47,74 @@ struct Cli {
     alive: bool,
 
     #[clap(long, short, action)]
-    /// Use thread parallelism
+    /// Use a parallel alpha-beta search on a thread pool
     thread: bool,
 
+    #[clap(long, action, requires = "thread")]
+    /// With `--thread`, use a Lazy SMP search instead of splitting the tree
+    lazy_smp: bool,
+
     #[clap(long, short, action)]
     /// Use a cache
     cache: bool,
+
+    #[clap(long)]
+    /// Bound the memory of the cache to this number of MiB, evicting the least recently used entries
+    cache_size: Option<usize>,
+
+    #[clap(long)]
+    /// Load the cache from this file if it exists, and save it there at the end of the game
+    cache_file: Option<PathBuf>,
+
+    #[clap(long, conflicts_with_all = ["cache_size", "cache_file"])]
+    /// Share the cache with other robots through the cache server at this address
+    cache_server: Option<String>,
+
+    #[clap(long)]
+    /// Seed of every random choice, drawn at random (and logged) if absent
+    seed: Option<u64>,
+
+    #[clap(long, default_value_t = 0)]
+    /// Number of plies played at random at the start of the game
+    opening: usize,
+
+    #[clap(long)]
+    /// N-tuple network (see the `train` binary) used instead of the naive evaluation
+    heuristic: Option<PathBuf>,
+}
+
+/// The evaluator asked on the command line, the search flags being shorthands for a spec
+fn spec(args: &Cli) -> Spec {
+    if let Some(spec) = &args.evaluator {
+        return spec.clone();
+    }
+    if let Some(ai) = args.ai {
+        return ai.spec();
+    }
+    let depth = args
+        .depth
+        .expect("A depth is required without an evaluator");
+    let name = if args.lazy_smp {
+        "lazysmp"
+    } else if args.thread {
+        "parallel"
+    } else {
+        "minmax"
+    };
+    let cache = if let Some(addr) = &args.cache_server {
+        format!(",cache=remote,cache_server={addr}")
+    } else if let Some(size) = args.cache_size {
+        format!(",cache=bounded,cache_mb={size}")
+    } else if args.thread || args.cache || args.cache_file.is_some() {
+        // The parallel searches share the cache between their threads, and the sequential one
+        // shares it with the handle saving it
+        String::from(",cache=sharded")
+    } else {
+        String::new()
+    };
+    format!("{name}:depth={depth}{cache}")
+        .parse()
+        .expect("The flags make a valid spec")
 }
 
 fn main() {
@@ -62,25 +137,47 @@ fn main() {
         }
 
         let mut game = match args.role {
-            Roles::Client => RemoteGame::new_client(args.remote_addr).await,
-            Roles::Host => RemoteGame::new_server(args.remote_addr).await,
+            Roles::Client => RemoteGame::new_client(args.remote_addr.clone()).await,
+            Roles::Host => RemoteGame::new_server(args.remote_addr.clone()).await,
         };
         info!("Player connected!");
 
-        let evaluator: &mut dyn SyncEvaluator = if args.thread {
-            if args.cache {
-                &mut ThreadedPolicy::from(MinMaxPolicyCached::<KnowledgeCacheMultiThread>::new(
-                    args.depth - 1,
-                ))
-            } else {
-                &mut ThreadedPolicy::from(MinMaxPolicy::new(args.depth - 1))
-            }
-        } else if args.cache {
-            &mut MinMaxPolicyCached::<KnowledgeCacheSingleThread>::new(args.depth)
-        } else {
-            &mut MinMaxPolicy::new(args.depth)
+        let seed = args.seed.unwrap_or_else(rand::random);
+        info!("Seed : {seed}");
+
+        let heuristic: SharedHeuristic = match &args.heuristic {
+            Some(path) => Arc::new(NTupleNetwork::load(path).expect("Can't load the heuristic")),
+            None => Arc::new(NaiveHeuristic),
         };
 
+        let spec = spec(&args);
+        let context = Context {
+            heuristic,
+            seed,
+            pool: None,
+        };
+        let built = Registry::default()
+            .build_sync(&spec, &context)
+            .unwrap_or_else(|e| panic!("Can't build the evaluator {spec} : {e}"));
+        info!("Evaluator : {spec}");
+        let cache = built.cache;
+        if let Some(cache) = &cache {
+            if let Some(capacity) = cache.capacity() {
+                info!("Cache capacity : {capacity} entries");
+            }
+            if let Some(path) = &args.cache_file
+                && path.exists()
+            {
+                match cache.load(path) {
+                    Ok(n) => info!("Loaded {n} entries from {}", path.display()),
+                    Err(e) => warn!("Can't load the cache file : {e}"),
+                }
+            }
+        } else if args.cache_file.is_some() {
+            warn!("The evaluator has no cache to save");
+        }
+        let evaluator = RandomOpening::new(built.evaluator, args.opening, seed);
+
         let mut buff = std::io::BufWriter::new(std::io::stdout());
 
         loop {
@@ -95,6 +192,9 @@ fn main() {
             info!("Think for {:} ms", (end - start).as_millis());
             info!("Playing {p:?}");
             info!("Estimation : {e:?}");
+            if let Some(cache) = &cache {
+                info!("Cache : {} entries, {}", cache.len(), cache.stats());
+            }
             let e = game.play(p.column()).await;
             match e {
                 Ok(v) => match v {
@@ -114,5 +214,12 @@ fn main() {
                 }
             }
         }
+
+        if let (Some(path), Some(cache)) = (&args.cache_file, cache) {
+            match cache.save(path) {
+                Ok(()) => info!("Saved {} entries into {}", cache.len(), path.display()),
+                Err(e) => error!("Can't save the cache file : {e}"),
+            }
+        }
     });
 }
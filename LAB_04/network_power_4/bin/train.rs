@@ -0,0 +1,73 @@
+use std::{num::NonZeroUsize, path::PathBuf, time::Instant};
+
+use clap::Parser;
+use log::info;
+use network_power_4::heuristic::{NTupleNetwork, TdConfig};
+
+#[derive(clap::Parser)]
+#[command(version, about)]
+/// Train a N-tuple network by TD(lambda) self-play
+struct Cli {
+    /// File where the network is saved
+    output: PathBuf,
+
+    #[clap(long, short, default_value_t = 10_000)]
+    /// Number of self-play games
+    games: usize,
+
+    #[clap(long, default_value_t = NonZeroUsize::new(1_000).unwrap())]
+    /// Log the outcomes and save the network every `report` games
+    report: NonZeroUsize,
+
+    #[clap(long)]
+    /// Continue the training of a saved network
+    resume: Option<PathBuf>,
+
+    #[clap(long, default_value_t = TdConfig::default().alpha)]
+    /// Learning rate
+    alpha: f64,
+
+    #[clap(long, default_value_t = TdConfig::default().lambda)]
+    /// Decay of the eligibility traces
+    lambda: f64,
+
+    #[clap(long, default_value_t = TdConfig::default().epsilon)]
+    /// Probability of an exploration play
+    epsilon: f64,
+
+    #[clap(long)]
+    /// Seed of the self-play games, drawn at random (and logged) if absent
+    seed: Option<u64>,
+}
+
+fn main() {
+    colog::init();
+    let args = Cli::parse();
+
+    let mut network = match &args.resume {
+        Some(path) => NTupleNetwork::load(path).expect("Can't load the network"),
+        None => NTupleNetwork::default(),
+    };
+    let seed = args.seed.unwrap_or_else(rand::random);
+    info!("Seed : {seed}");
+
+    let mut played = 0;
+    while played < args.games {
+        let games = args.report.get().min(args.games - played);
+        let config = TdConfig {
+            alpha: args.alpha,
+            lambda: args.lambda,
+            epsilon: args.epsilon,
+            // A different stream for each batch, still derived from the seed
+            seed: seed.wrapping_add(played as u64),
+        };
+        let start = Instant::now();
+        let report = network.train(games, &config);
+        played += games;
+        info!(
+            "{played} games, {:} ms : {report:?}",
+            start.elapsed().as_millis()
+        );
+        network.save(&args.output).expect("Can't save the network");
+    }
+}
@@ -1,46 +1,112 @@
 use std::{
-    cell::Cell,
-    thread::{self, JoinHandle},
     future::Future,
-    task::{Poll, Context},
+    panic::{self, AssertUnwindSafe},
     pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    thread,
 };
 
-pub struct BlockingFuture<R, F: FnOnce() -> R> {
-    funct: Cell<Option<F>>,
-    thread: Cell<Option<*mut JoinHandle<R>>>,
+use crate::thread_pool::JoinError;
+
+/// A future running a blocking function on its own thread
+///
+/// The thread is spawned on the first poll, and wakes the task once the function returns. A panic of
+/// the function resolves the future to [`JoinError::Panic`]. Dropping the future detaches the thread,
+/// whose result is then dropped.
+pub struct BlockingFuture<R, F> {
+    funct: Option<F>,
+    /// Shared with the thread, once spawned
+    state: Option<Arc<Mutex<State<R>>>>,
+}
+
+/// The result of the function once over, or else the waker of the last poll
+struct State<R> {
+    result: Option<thread::Result<R>>,
+    waker: Option<Waker>,
 }
 
 impl<R, F: FnOnce() -> R> BlockingFuture<R, F> {
     pub fn new(f: F) -> Self {
         Self {
-            funct: Cell::new(Some(f)),
-            thread: Cell::new(None),
+            funct: Some(f),
+            state: None,
         }
     }
 }
 
+// The function is only moved out, never pinned
+impl<R, F> Unpin for BlockingFuture<R, F> {}
+
 impl<R: Send + 'static, F: FnOnce() -> R + Send + 'static> Future for BlockingFuture<R, F> {
-    type Output = R;
-
-    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
-        // Init a thread if necessary
-        if self.thread.get().is_none() {
-            if let Some(f) = self.funct.take() {
-                let handle = Box::new(thread::spawn(f));
-                self.thread.set(Some(Box::into_raw(handle)));
-            }
+    type Output = Result<R, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        // Spawn the thread on the first poll
+        if let Some(f) = this.funct.take() {
+            let state = Arc::new(Mutex::new(State {
+                result: None,
+                waker: None,
+            }));
+            let thread_state = state.clone();
+            thread::spawn(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(f));
+                let waker = {
+                    let mut state = thread_state.lock().unwrap();
+                    state.result = Some(result);
+                    state.waker.take()
+                };
+                // Woken out of the lock, the task may poll at once
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            });
+            this.state = Some(state);
         }
 
-        // Verify if the thread was finished
-        if let Some(ptr) = self.thread.get() {
-            let boxed_handle = unsafe { Box::from_raw(ptr) };
-            match boxed_handle.join() {
-                Ok(result) => Poll::Ready(result),
-                Err(_) => panic!("Thread panicked"),
+        let state = this
+            .state
+            .as_ref()
+            .expect("BlockingFuture polled after completion");
+        // The waker is stored under the same lock as the result, so the wake up can't be missed
+        let mut guard = state.lock().unwrap();
+        match guard.result.take() {
+            Some(result) => {
+                drop(guard);
+                this.state = None;
+                Poll::Ready(result.map_err(JoinError::Panic))
+            }
+            None => {
+                match &mut guard.waker {
+                    Some(waker) if waker.will_wake(cx.waker()) => {}
+                    waker => *waker = Some(cx.waker().clone()),
+                }
+                Poll::Pending
             }
-        } else {
-            Poll::Pending
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker;
+
+    #[test]
+    fn poll_never_blocks() {
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let mut future = BlockingFuture::new(move || rx.recv().unwrap());
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        // The function waits for the test, polling it twice must not block nor spawn twice
+        assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+        assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+        tx.send(()).unwrap();
+        assert!(crate::executor::block_on(future).is_ok());
+
+        let panicked = BlockingFuture::new(|| panic!("boom"));
+        let error = crate::executor::block_on(panicked).unwrap_err();
+        assert_eq!(error.to_string(), "the job panicked : boom");
+    }
+}
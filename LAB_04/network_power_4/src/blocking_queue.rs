@@ -0,0 +1,335 @@
+//! Bounded blocking queues, the Rust port of the blocking queues of the third lab
+//!
+//! Both flavours implement [`BlockingQueue`] : [`CondBlockingQueue`] waits on condition variables, and
+//! [`SemBlockingQueue`] counts the free and the full slots with semaphores. [`RingQueue`] is a lock-free
+//! alternative, which only parks the threads that have to wait.
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use parking_lot::{Condvar, Mutex};
+
+mod ring;
+
+pub use ring::RingQueue;
+
+/// A first in first out queue with a fixed capacity, shared between producers and consumers
+///
+/// Each operation comes in three forms : blocking until possible (`put`, `get`), giving up at once
+/// (`try_put`, `try_get`), or giving up after a timeout (`offer`, `poll`).
+pub trait BlockingQueue<T>: Send + Sync {
+    /// Insert `item`, waiting for a free slot
+    fn put(&self, item: T);
+
+    /// Take the oldest item, waiting for one
+    fn get(&self) -> T;
+
+    /// Insert `item` if a slot is free, or else give it back
+    fn try_put(&self, item: T) -> Result<(), T>;
+
+    /// Take the oldest item, if any
+    fn try_get(&self) -> Option<T>;
+
+    /// Insert `item`, waiting at most `timeout` for a free slot, or else give it back
+    fn offer(&self, item: T, timeout: Duration) -> Result<(), T>;
+
+    /// Take the oldest item, waiting at most `timeout` for one
+    fn poll(&self, timeout: Duration) -> Option<T>;
+
+    /// Number of items in the queue
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maximal number of items in the queue
+    fn capacity(&self) -> usize;
+}
+
+/// A [`BlockingQueue`] whose producers wait on `not_full` and consumers on `not_empty`
+#[derive(Debug)]
+pub struct CondBlockingQueue<T> {
+    buffer: Mutex<VecDeque<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+}
+
+impl<T> CondBlockingQueue<T> {
+    /// # Panics
+    ///
+    /// If `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "A blocking queue needs at least one slot");
+        Self {
+            buffer: Mutex::new(VecDeque::with_capacity(capacity)),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity,
+        }
+    }
+
+    /// Insert into a queue with a free slot
+    fn push(&self, buffer: &mut VecDeque<T>, item: T) {
+        buffer.push_back(item);
+        self.not_empty.notify_one();
+    }
+
+    /// Take from a queue with an item
+    fn pop(&self, buffer: &mut VecDeque<T>) -> T {
+        self.not_full.notify_one();
+        buffer.pop_front().expect("The queue has an item")
+    }
+}
+
+impl<T: Send> BlockingQueue<T> for CondBlockingQueue<T> {
+    fn put(&self, item: T) {
+        let mut buffer = self.buffer.lock();
+        while buffer.len() == self.capacity {
+            self.not_full.wait(&mut buffer);
+        }
+        self.push(&mut buffer, item);
+    }
+
+    fn get(&self) -> T {
+        let mut buffer = self.buffer.lock();
+        while buffer.is_empty() {
+            self.not_empty.wait(&mut buffer);
+        }
+        self.pop(&mut buffer)
+    }
+
+    fn try_put(&self, item: T) -> Result<(), T> {
+        let mut buffer = self.buffer.lock();
+        if buffer.len() == self.capacity {
+            return Err(item);
+        }
+        self.push(&mut buffer, item);
+        Ok(())
+    }
+
+    fn try_get(&self) -> Option<T> {
+        let mut buffer = self.buffer.lock();
+        (!buffer.is_empty()).then(|| self.pop(&mut buffer))
+    }
+
+    fn offer(&self, item: T, timeout: Duration) -> Result<(), T> {
+        let deadline = Instant::now() + timeout;
+        let mut buffer = self.buffer.lock();
+        while buffer.len() == self.capacity {
+            if self.not_full.wait_until(&mut buffer, deadline).timed_out()
+                && buffer.len() == self.capacity
+            {
+                return Err(item);
+            }
+        }
+        self.push(&mut buffer, item);
+        Ok(())
+    }
+
+    fn poll(&self, timeout: Duration) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+        let mut buffer = self.buffer.lock();
+        while buffer.is_empty() {
+            if self.not_empty.wait_until(&mut buffer, deadline).timed_out() && buffer.is_empty() {
+                return None;
+            }
+        }
+        Some(self.pop(&mut buffer))
+    }
+
+    fn len(&self) -> usize {
+        self.buffer.lock().len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+/// A counting semaphore
+#[derive(Debug)]
+struct Semaphore {
+    permits: Mutex<usize>,
+    available: Condvar,
+}
+
+impl Semaphore {
+    fn new(permits: usize) -> Self {
+        Self {
+            permits: Mutex::new(permits),
+            available: Condvar::new(),
+        }
+    }
+
+    fn acquire(&self) {
+        let mut permits = self.permits.lock();
+        while *permits == 0 {
+            self.available.wait(&mut permits);
+        }
+        *permits -= 1;
+    }
+
+    fn try_acquire(&self) -> bool {
+        let mut permits = self.permits.lock();
+        let acquired = *permits > 0;
+        if acquired {
+            *permits -= 1;
+        }
+        acquired
+    }
+
+    /// Acquire a permit, waiting until `deadline` at most
+    fn acquire_until(&self, deadline: Instant) -> bool {
+        let mut permits = self.permits.lock();
+        while *permits == 0 {
+            if self
+                .available
+                .wait_until(&mut permits, deadline)
+                .timed_out()
+                && *permits == 0
+            {
+                return false;
+            }
+        }
+        *permits -= 1;
+        true
+    }
+
+    fn release(&self) {
+        *self.permits.lock() += 1;
+        self.available.notify_one();
+    }
+}
+
+/// A [`BlockingQueue`] counting its free and full slots with semaphores
+///
+/// A slot is acquired before taking the lock of the buffer, which is only held to move the item.
+#[derive(Debug)]
+pub struct SemBlockingQueue<T> {
+    buffer: Mutex<VecDeque<T>>,
+    empty_slots: Semaphore,
+    full_slots: Semaphore,
+    capacity: usize,
+}
+
+impl<T> SemBlockingQueue<T> {
+    /// # Panics
+    ///
+    /// If `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "A blocking queue needs at least one slot");
+        Self {
+            buffer: Mutex::new(VecDeque::with_capacity(capacity)),
+            empty_slots: Semaphore::new(capacity),
+            full_slots: Semaphore::new(0),
+            capacity,
+        }
+    }
+
+    /// Insert into an acquired empty slot
+    fn push(&self, item: T) {
+        self.buffer.lock().push_back(item);
+        self.full_slots.release();
+    }
+
+    /// Take from an acquired full slot
+    fn pop(&self) -> T {
+        let item = self.buffer.lock().pop_front();
+        self.empty_slots.release();
+        item.expect("A full slot holds an item")
+    }
+}
+
+impl<T: Send> BlockingQueue<T> for SemBlockingQueue<T> {
+    fn put(&self, item: T) {
+        self.empty_slots.acquire();
+        self.push(item);
+    }
+
+    fn get(&self) -> T {
+        self.full_slots.acquire();
+        self.pop()
+    }
+
+    fn try_put(&self, item: T) -> Result<(), T> {
+        if !self.empty_slots.try_acquire() {
+            return Err(item);
+        }
+        self.push(item);
+        Ok(())
+    }
+
+    fn try_get(&self) -> Option<T> {
+        self.full_slots.try_acquire().then(|| self.pop())
+    }
+
+    fn offer(&self, item: T, timeout: Duration) -> Result<(), T> {
+        if !self.empty_slots.acquire_until(Instant::now() + timeout) {
+            return Err(item);
+        }
+        self.push(item);
+        Ok(())
+    }
+
+    fn poll(&self, timeout: Duration) -> Option<T> {
+        self.full_slots
+            .acquire_until(Instant::now() + timeout)
+            .then(|| self.pop())
+    }
+
+    fn len(&self) -> usize {
+        self.buffer.lock().len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    use super::*;
+
+    fn check<Q: BlockingQueue<usize>>(queue: Q) {
+        assert_eq!(queue.try_get(), None);
+        assert_eq!(queue.poll(Duration::from_millis(5)), None);
+        queue.put(1);
+        assert_eq!(queue.try_put(2), Ok(()));
+        assert_eq!(queue.try_put(3), Err(3));
+        assert_eq!(queue.offer(3, Duration::from_millis(5)), Err(3));
+        assert_eq!(queue.get(), 1);
+        assert_eq!(queue.poll(Duration::ZERO), Some(2));
+
+        // Producers block on the full queue until the consumers take the items
+        let sum = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for p in 0..4 {
+                let queue = &queue;
+                s.spawn(move || (0..100).for_each(|i| queue.put(p * 100 + i)));
+            }
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        sum.fetch_add(queue.get(), Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+        assert_eq!(sum.into_inner(), (0..400).sum::<usize>());
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn both_flavours() {
+        check(CondBlockingQueue::new(2));
+        check(SemBlockingQueue::new(2));
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use parking_lot::{Condvar, Mutex};
+
+use super::BlockingQueue;
+
+/// Number of failed attempts before a blocking operation parks
+const SPINS: usize = 64;
+
+/// Keeps the positions of the producers and of the consumers on distinct cache lines
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+struct Slot<T> {
+    /// The position of the next push into the slot, or the one plus one of the next pop from it
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Threads waiting for the ring to change
+///
+/// The attempts run out of the lock, as a successful one notifies the other side of the ring.
+struct Parking {
+    /// Number of changes, a waiter only sleeps if none happened since its last attempt
+    epoch: AtomicUsize,
+    waiters: AtomicUsize,
+    lock: Mutex<()>,
+    changed: Condvar,
+}
+
+impl Parking {
+    fn new() -> Self {
+        Self {
+            epoch: AtomicUsize::new(0),
+            waiters: AtomicUsize::new(0),
+            lock: Mutex::new(()),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Retry `attempt` until it succeeds or `deadline` passes, sleeping between the attempts
+    fn wait<R>(
+        &self,
+        deadline: Option<Instant>,
+        mut attempt: impl FnMut() -> Option<R>,
+    ) -> Option<R> {
+        for _ in 0..SPINS {
+            if let Some(r) = attempt() {
+                return Some(r);
+            }
+            std::hint::spin_loop();
+        }
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let result = loop {
+            // Either the attempt sees the change, or the waiter sees the new epoch
+            let epoch = self.epoch.load(Ordering::SeqCst);
+            if let Some(r) = attempt() {
+                break Some(r);
+            }
+            let mut lock = self.lock.lock();
+            if self.epoch.load(Ordering::SeqCst) != epoch {
+                continue;
+            }
+            match deadline {
+                None => self.changed.wait(&mut lock),
+                Some(deadline) => {
+                    if self.changed.wait_until(&mut lock, deadline).timed_out() {
+                        drop(lock);
+                        break attempt();
+                    }
+                }
+            }
+        };
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+
+    fn notify(&self) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            // The lock is held by a waiter from its check of the epoch until its sleep
+            let _lock = self.lock.lock();
+            self.changed.notify_one();
+        }
+    }
+}
+
+/// A lock-free bounded multi-producer multi-consumer queue
+///
+/// The items are stored in a ring of slots, each with a sequence number telling whether it is ready
+/// for a push or for a pop at a given position (D. Vyukov's bounded queue). Producers and consumers
+/// claim their positions with a compare and swap, so `try_put` and `try_get` never lock. The blocking
+/// operations spin a little, then park until the other side notifies a change.
+///
+/// The capacity is rounded up to a power of two, at least 2.
+pub struct RingQueue<T> {
+    slots: Box<[Slot<T>]>,
+    mask: usize,
+    /// Position of the next push
+    head: CachePadded<AtomicUsize>,
+    /// Position of the next pop
+    tail: CachePadded<AtomicUsize>,
+    /// Producers waiting for a free slot
+    not_full: Parking,
+    /// Consumers waiting for an item
+    not_empty: Parking,
+}
+
+// SAFETY: an item is moved into a slot by a single producer and out of it by a single consumer, the
+// claims of the positions and the sequence numbers ordering the accesses to the slot
+unsafe impl<T: Send> Send for RingQueue<T> {}
+unsafe impl<T: Send> Sync for RingQueue<T> {}
+
+impl<T> RingQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+        Self {
+            slots: (0..capacity)
+                .map(|i| Slot {
+                    sequence: AtomicUsize::new(i),
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect(),
+            mask: capacity - 1,
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+            not_full: Parking::new(),
+            not_empty: Parking::new(),
+        }
+    }
+
+    fn push(&self, item: T) -> Result<(), T> {
+        let mut pos = self.head.0.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match sequence.wrapping_sub(pos) as isize {
+                0 => match self.head.0.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: the position is claimed, and the slot was emptied by its last pop
+                        unsafe { (*slot.value.get()).write(item) };
+                        slot.sequence.store(pos.wrapping_add(1), Ordering::Release);
+                        self.not_empty.notify();
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                },
+                // The slot still holds the item of the previous lap
+                d if d < 0 => return Err(item),
+                _ => pos = self.head.0.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        let mut pos = self.tail.0.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match sequence.wrapping_sub(pos.wrapping_add(1)) as isize {
+                0 => match self.tail.0.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: the position is claimed, and the slot was filled by its push
+                        let item = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.sequence
+                            .store(pos.wrapping_add(self.mask + 1), Ordering::Release);
+                        self.not_full.notify();
+                        return Some(item);
+                    }
+                    Err(current) => pos = current,
+                },
+                // The slot is not filled yet
+                d if d < 0 => return None,
+                _ => pos = self.tail.0.load(Ordering::Relaxed),
+            }
+        }
+    }
+}
+
+impl<T> Drop for RingQueue<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+impl<T: Send> BlockingQueue<T> for RingQueue<T> {
+    fn put(&self, item: T) {
+        let mut item = Some(item);
+        self.not_full.wait(None, || {
+            self.push(item.take().unwrap())
+                .map_err(|back| item = Some(back))
+                .ok()
+        });
+    }
+
+    fn get(&self) -> T {
+        self.not_empty
+            .wait(None, || self.pop())
+            .expect("Waiting without a deadline always succeeds")
+    }
+
+    fn try_put(&self, item: T) -> Result<(), T> {
+        self.push(item)
+    }
+
+    fn try_get(&self) -> Option<T> {
+        self.pop()
+    }
+
+    fn offer(&self, item: T, timeout: Duration) -> Result<(), T> {
+        let mut item = Some(item);
+        let pushed = self.not_full.wait(Some(Instant::now() + timeout), || {
+            self.push(item.take().unwrap())
+                .map_err(|back| item = Some(back))
+                .ok()
+        });
+        match pushed {
+            Some(()) => Ok(()),
+            None => Err(item.unwrap()),
+        }
+    }
+
+    fn poll(&self, timeout: Duration) -> Option<T> {
+        self.not_empty
+            .wait(Some(Instant::now() + timeout), || self.pop())
+    }
+
+    fn len(&self) -> usize {
+        let tail = self.tail.0.load(Ordering::SeqCst);
+        let head = self.head.0.load(Ordering::SeqCst);
+        head.wrapping_sub(tail).min(self.mask + 1)
+    }
+
+    fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicU8, thread};
+
+    use super::*;
+
+    #[test]
+    fn no_item_lost_or_duplicated() {
+        const PRODUCERS: usize = 4;
+        const ITEMS: usize = 20_000;
+        let ring = RingQueue::new(8);
+        let seen: Vec<_> = (0..PRODUCERS * ITEMS).map(|_| AtomicU8::new(0)).collect();
+        thread::scope(|s| {
+            for p in 0..PRODUCERS {
+                let ring = &ring;
+                s.spawn(move || {
+                    for i in 0..ITEMS {
+                        // Mix the blocking and the non-blocking pushes
+                        match i % 2 {
+                            0 => ring.put(p * ITEMS + i),
+                            _ => {
+                                let mut item = p * ITEMS + i;
+                                while let Err(back) = ring.try_put(item) {
+                                    item = back;
+                                    thread::yield_now();
+                                }
+                            }
+                        }
+                    }
+                });
+            }
+            for _ in 0..PRODUCERS {
+                s.spawn(|| {
+                    for _ in 0..ITEMS {
+                        seen[ring.get()].fetch_add(1, Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+        assert!(seen.iter().all(|n| n.load(Ordering::Relaxed) == 1));
+        assert!(ring.is_empty());
+        assert_eq!(ring.poll(Duration::from_millis(1)), None);
+    }
+}
@@ -1,16 +1,37 @@
 //! Caches to store information
+mod bounded;
 mod multi_thread;
+mod persist;
+mod remote;
+mod sharded;
 mod single_thread;
+mod stats;
+
+use std::path::Path;
 
 use crate::{End, Player, game::board::Board};
 
+pub use bounded::KnowledgeCacheBounded;
 pub use multi_thread::KnowledgeCacheMultiThread;
+pub use persist::CacheFileError;
+pub use remote::{KnowledgeCacheRemote, serve_client};
+pub use sharded::KnowledgeCacheSharded;
 pub use single_thread::KnowledgeCacheSingleThread;
+pub use stats::CacheStats;
+
+/// An entry of a cache : the position and the player to move, the best column and the ending
+pub type Entry = ((Board, Player), (usize, End));
 
 /// A cache implementation
 pub trait KnowledgeCache {
     /// Lookup the already calculated ending from a `Board` and a `Player`, with the best column to play
     fn lookup(&self, board_state: Board, player: Player) -> Option<(usize, End)>;
+    /// Lookup several positions at once, which saves round trips with a remote cache
+    fn lookup_batch(&self, keys: &[(Board, Player)]) -> Vec<Option<(usize, End)>> {
+        keys.iter()
+            .map(|&(board_state, player)| self.lookup(board_state, player))
+            .collect()
+    }
     /// Store a newly calculated best move for a `Player` from a `Board`
     fn remember(
         &self,
@@ -19,12 +40,36 @@ pub trait KnowledgeCache {
         best_choice: usize,
         projected_ending: End,
     );
+    /// Store entries computed elsewhere, such as the ones of a file, without counting them in the
+    /// statistics
+    fn restore(&self, entries: &[Entry]);
     /// Empty the cache
     fn clean(&mut self);
     /// Number of entry in a cache
     fn len(&self) -> usize;
+    /// Maximal number of entry in the cache, `None` if it is unbounded
+    fn capacity(&self) -> Option<usize> {
+        None
+    }
+    /// Counters of the operations on the cache
+    fn stats(&self) -> CacheStats;
     /// Is the cache empty
     fn is_empty(&self) -> bool {
         self.len() == 0
     }
+    /// All the entries of the cache
+    fn entries(&self) -> Vec<Entry>;
+    /// Save the entries of the cache into a file
+    fn save(&self, path: &Path) -> Result<(), CacheFileError> {
+        persist::save(path, &self.entries())
+    }
+    /// Add the entries saved in a file to the cache, returning their number
+    ///
+    /// A file saved for other board dimensions is rejected. The loaded entries are not counted as
+    /// inserts.
+    fn load(&self, path: &Path) -> Result<usize, CacheFileError> {
+        let entries = persist::load(path)?;
+        self.restore(&entries);
+        Ok(entries.len())
+    }
 }
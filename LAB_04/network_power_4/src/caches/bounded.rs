@@ -0,0 +1,248 @@
+use parking_lot::Mutex;
+use std::{collections::HashMap, mem::size_of, sync::Arc};
+
+use super::{CacheStats, Entry, KnowledgeCache, stats::Counters};
+use crate::{End, Player, game::board::Board};
+
+type Key = (Board, Player);
+
+/// Index of the absent node in the recency list
+const NIL: usize = usize::MAX;
+
+/// Default memory budget of a bounded cache
+const DEFAULT_MEMORY: usize = 64 << 20;
+
+#[derive(Debug, Clone)]
+struct Node {
+    key: Key,
+    value: (usize, End),
+    /// More recently used neighbour
+    prev: usize,
+    /// Less recently used neighbour
+    next: usize,
+}
+
+/// A least recently used map, with a fixed number of entries
+///
+/// The nodes are stored in a `Vec` and linked by their indices, from the most recently used (`head`)
+/// to the least recently used (`tail`). Once full, the slot of the tail is reused for the new entry.
+#[derive(Debug, Clone)]
+struct Lru {
+    map: HashMap<Key, usize>,
+    nodes: Vec<Node>,
+    head: usize,
+    tail: usize,
+    capacity: usize,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Self {
+            map: HashMap::new(),
+            nodes: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            capacity,
+        }
+    }
+
+    fn detach(&mut self, idx: usize) {
+        let (prev, next) = (self.nodes[idx].prev, self.nodes[idx].next);
+        match prev {
+            NIL => self.head = next,
+            p => self.nodes[p].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            n => self.nodes[n].prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, idx: usize) {
+        self.nodes[idx].prev = NIL;
+        self.nodes[idx].next = self.head;
+        match self.head {
+            NIL => self.tail = idx,
+            h => self.nodes[h].prev = idx,
+        }
+        self.head = idx;
+    }
+
+    fn get(&mut self, key: &Key) -> Option<(usize, End)> {
+        let idx = *self.map.get(key)?;
+        self.detach(idx);
+        self.push_front(idx);
+        Some(self.nodes[idx].value)
+    }
+
+    fn insert(&mut self, key: Key, value: (usize, End), stats: &Counters) {
+        if let Some(&idx) = self.map.get(&key) {
+            self.nodes[idx].value = value;
+            self.detach(idx);
+            self.push_front(idx);
+            stats.insert(true);
+            return;
+        }
+        stats.insert(false);
+        if self.capacity == 0 {
+            // The new entry is evicted at once
+            stats.evict();
+            return;
+        }
+        let node = Node {
+            key,
+            value,
+            prev: NIL,
+            next: NIL,
+        };
+        let idx = if self.nodes.len() < self.capacity {
+            self.nodes.push(node);
+            self.nodes.len() - 1
+        } else {
+            // Evict the least recently used entry
+            stats.evict();
+            let idx = self.tail;
+            self.detach(idx);
+            self.map.remove(&self.nodes[idx].key);
+            self.nodes[idx] = node;
+            idx
+        };
+        self.map.insert(key, idx);
+        self.push_front(idx);
+    }
+
+    fn clear(&mut self) {
+        self.map.clear();
+        self.nodes.clear();
+        self.head = NIL;
+        self.tail = NIL;
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A thread safe cache with a memory budget
+///
+/// When the cache is full, the least recently used entry (looked up or remembered) is evicted.
+/// Clones share the same entries.
+pub struct KnowledgeCacheBounded {
+    inner: Arc<Mutex<Lru>>,
+    stats: Arc<Counters>,
+}
+
+impl KnowledgeCacheBounded {
+    /// Approximate memory used by one entry : the node, and the slot of the `HashMap` (at its maximal load factor)
+    pub const ENTRY_SIZE: usize = size_of::<Node>() + (size_of::<(Key, usize)>() + 1) * 8 / 7;
+
+    /// A cache holding at most `capacity` entries
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Lru::new(capacity))),
+            stats: Arc::default(),
+        }
+    }
+
+    /// A cache using approximately at most `bytes` bytes of memory
+    pub fn with_memory(bytes: usize) -> Self {
+        Self::with_capacity(bytes / Self::ENTRY_SIZE)
+    }
+}
+
+impl Default for KnowledgeCacheBounded {
+    fn default() -> Self {
+        Self::with_memory(DEFAULT_MEMORY)
+    }
+}
+
+impl KnowledgeCache for KnowledgeCacheBounded {
+    fn lookup(&self, board_state: Board, player: Player) -> Option<(usize, End)> {
+        // A lookup refreshes the entry, so it needs an exclusive access
+        let found = self.inner.lock().get(&(board_state, player));
+        self.stats.lookup(found)
+    }
+
+    fn remember(
+        &self,
+        board_state: Board,
+        player: Player,
+        best_choice: usize,
+        projected_ending: End,
+    ) {
+        self.inner.lock().insert(
+            (board_state, player),
+            (best_choice, projected_ending),
+            &self.stats,
+        );
+    }
+
+    fn restore(&self, entries: &[Entry]) {
+        // The entries evicted meanwhile are not counted either
+        let uncounted = Counters::default();
+        let mut lru = self.inner.lock();
+        for &(key, value) in entries {
+            lru.insert(key, value, &uncounted);
+        }
+    }
+
+    fn clean(&mut self) {
+        self.inner.lock().clear();
+    }
+
+    fn len(&self) -> usize {
+        self.inner.lock().map.len()
+    }
+
+    /// The entries from the least to the most recently used, so loading them keeps their recency
+    fn entries(&self) -> Vec<Entry> {
+        let lru = self.inner.lock();
+        let mut entries = Vec::with_capacity(lru.nodes.len());
+        let mut idx = lru.tail;
+        while idx != NIL {
+            let node = &lru.nodes[idx];
+            entries.push((node.key, node.value));
+            idx = node.prev;
+        }
+        entries
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.stats.snapshot()
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        Some(self.inner.lock().capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Game;
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        let boards: Vec<_> = (0..3)
+            .map(|c| {
+                let mut game = Game::default();
+                game.play(c).unwrap();
+                game.board()
+            })
+            .collect();
+        let cache = KnowledgeCacheBounded::with_capacity(2);
+        cache.remember(boards[0], Player::SECOND, 0, End::Stall);
+        cache.remember(boards[1], Player::SECOND, 1, End::Stall);
+        assert!(cache.lookup(boards[0], Player::SECOND).is_some());
+        cache.remember(boards[2], Player::SECOND, 2, End::Stall);
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(
+            cache.lookup(boards[0], Player::SECOND),
+            Some((0, End::Stall))
+        );
+        assert_eq!(cache.lookup(boards[1], Player::SECOND), None);
+        assert_eq!(cache.stats().evictions, 1);
+        assert_eq!(
+            cache.lookup(boards[2], Player::SECOND),
+            Some((2, End::Stall))
+        );
+    }
+}
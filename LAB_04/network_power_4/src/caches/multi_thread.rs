@@ -1,8 +1,11 @@
-use parking_lot::RwLock;
-use std::{collections::BTreeMap, sync::Arc};
+use std::collections::BTreeMap;
 
-use super::KnowledgeCache;
-use crate::{End, Player, game::board::Board};
+use super::{CacheStats, Entry, KnowledgeCache, stats::Counters};
+use crate::{
+    End, Player,
+    game::board::Board,
+    sync::{Arc, RwLock},
+};
 
 // To simplify the warning (warning: very complex type used. Consider factoring parts into `type` definitions) from clippy
 type SharedCache = Arc<RwLock<BTreeMap<(Board, Player), (usize, End)>>>;
@@ -13,13 +16,15 @@ pub struct KnowledgeCacheMultiThread {
     // Internal cache protected by RwLock for concurrent access
     // inner: Arc<RwLock<BTreeMap<(Board, Player), (usize, End)>>>, // too complex to clippy
     inner: SharedCache,
+    // Shared by the clones, like the entries
+    stats: Arc<Counters>,
 }
 
 impl KnowledgeCache for KnowledgeCacheMultiThread {
     fn lookup(&self, board_state: Board, player: Player) -> Option<(usize, End)> {
         // Acquire read access to the cache
-        let map = self.inner.read();
-        map.get(&(board_state, player)).copied()
+        let map = self.inner.read().unwrap();
+        self.stats.lookup(map.get(&(board_state, player)).copied())
     }
 
     fn remember(
@@ -30,18 +35,37 @@ impl KnowledgeCache for KnowledgeCacheMultiThread {
         projected_ending: End,
     ) {
         // Acquire write access and insert the result
-        let mut map = self.inner.write();
-        map.insert((board_state, player), (best_choice, projected_ending));
+        let mut map = self.inner.write().unwrap();
+        let old = map.insert((board_state, player), (best_choice, projected_ending));
+        self.stats.insert(old.is_some());
+    }
+
+    fn restore(&self, entries: &[Entry]) {
+        self.inner.write().unwrap().extend(entries.iter().copied());
     }
 
     fn len(&self) -> usize {
         // Get length with read access
-        self.inner.read().len()
+        self.inner.read().unwrap().len()
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        // Copy the entries with read access
+        self.inner
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (*k, *v))
+            .collect()
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.stats.snapshot()
     }
 
     fn clean(&mut self) {
         // Clear cache with write access
-        self.inner.write().clear();
+        self.inner.write().unwrap().clear();
     }
 }
 
@@ -50,6 +74,29 @@ impl Default for KnowledgeCacheMultiThread {
         // Initialize with empty BTreeMap inside Arc<RwLock<>>
         Self {
             inner: Arc::new(RwLock::new(BTreeMap::new())),
+            stats: Arc::default(),
         }
     }
 }
+
+#[cfg(all(test, power4_loom))]
+mod loom_models {
+    use super::*;
+    use crate::{Game, sync::thread};
+
+    #[test]
+    fn concurrent_remembers_are_all_counted() {
+        loom::model(|| {
+            let board = Game::default().board();
+            let cache = KnowledgeCacheMultiThread::default();
+            let other = cache.clone();
+            let writer = thread::spawn(move || other.remember(board, Player::FIRST, 0, End::Stall));
+            cache.remember(board, Player::FIRST, 1, End::Stall);
+            writer.join().unwrap();
+
+            assert!(cache.lookup(board, Player::FIRST).is_some());
+            let stats = cache.stats();
+            assert_eq!((stats.inserts, stats.overwrites), (1, 1));
+        });
+    }
+}
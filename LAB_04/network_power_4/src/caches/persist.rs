@@ -0,0 +1,297 @@
+//! Binary file format of the caches
+//!
+//! The file starts with a header : the magic bytes, the version of the format and the dimensions of the
+//! board (height, width and power), then the number of entries. Each entry is the board (two bits per
+//! cell, line by line), the player to move, the best column and the ending.
+//!
+//! The same encoding is used by the cache server protocol.
+
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{End, HEIGHT, POWER, Player, WIDTH, game::board::Board};
+
+use super::Entry;
+
+const MAGIC: &[u8; 4] = b"P4KC";
+/// Version of the format, increased at each incompatible change
+const VERSION: u16 = 1;
+const CELLS: usize = HEIGHT * WIDTH;
+const BOARD_BYTES: usize = CELLS.div_ceil(4);
+
+#[derive(Debug)]
+/// An error while reading or writing a cache file
+pub enum CacheFileError {
+    Io(std::io::Error),
+    /// The file is not a cache file, or is corrupted
+    Format(&'static str),
+    /// The file was written with another version of the format
+    Version(u16),
+    /// The file was written for another board
+    Dimensions {
+        height: usize,
+        width: usize,
+        power: usize,
+    },
+}
+
+impl Display for CacheFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Format(reason) => write!(f, "not a valid cache file : {reason}"),
+            Self::Version(version) => write!(
+                f,
+                "written with the version {version} of the format, not {VERSION}"
+            ),
+            Self::Dimensions {
+                height,
+                width,
+                power,
+            } => write!(
+                f,
+                "written for a {width}x{height} board with {power} to align, not {WIDTH}x{HEIGHT} with {POWER}"
+            ),
+        }
+    }
+}
+
+impl Error for CacheFileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CacheFileError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+fn player_code(player: Player) -> u8 {
+    match player {
+        Player::FIRST => 1,
+        Player::SECOND => 2,
+    }
+}
+
+fn player_from(code: u8) -> Result<Player, CacheFileError> {
+    match code {
+        1 => Ok(Player::FIRST),
+        2 => Ok(Player::SECOND),
+        _ => Err(CacheFileError::Format("invalid player")),
+    }
+}
+
+fn encode_board(board: &Board) -> [u8; BOARD_BYTES] {
+    let mut bytes = [0; BOARD_BYTES];
+    for i in 0..CELLS {
+        if let Some(p) = board[(i / WIDTH, i % WIDTH)] {
+            bytes[i / 4] |= player_code(p) << (2 * (i % 4));
+        }
+    }
+    bytes
+}
+
+fn decode_board(bytes: &[u8; BOARD_BYTES]) -> Result<Board, CacheFileError> {
+    let mut cells = [[None; WIDTH]; HEIGHT];
+    for i in 0..CELLS {
+        cells[i / WIDTH][i % WIDTH] = match (bytes[i / 4] >> (2 * (i % 4))) & 0b11 {
+            0 => None,
+            code => Some(player_from(code)?),
+        };
+    }
+    Ok(Board::from_cells(cells))
+}
+
+/// Write the header : magic bytes, version and dimensions
+pub(super) fn write_header<W: Write>(out: &mut W) -> Result<(), CacheFileError> {
+    out.write_all(MAGIC)?;
+    out.write_all(&VERSION.to_le_bytes())?;
+    out.write_all(&[HEIGHT as u8, WIDTH as u8, POWER as u8])?;
+    Ok(())
+}
+
+/// Read and check the header
+pub(super) fn read_header<R: Read>(input: &mut R) -> Result<(), CacheFileError> {
+    let mut magic = [0; 4];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(CacheFileError::Format("not a cache file"));
+    }
+    let mut version = [0; 2];
+    input.read_exact(&mut version)?;
+    let version = u16::from_le_bytes(version);
+    if version != VERSION {
+        return Err(CacheFileError::Version(version));
+    }
+    let mut dimensions = [0; 3];
+    input.read_exact(&mut dimensions)?;
+    let [height, width, power] = dimensions.map(usize::from);
+    if (height, width, power) != (HEIGHT, WIDTH, POWER) {
+        return Err(CacheFileError::Dimensions {
+            height,
+            width,
+            power,
+        });
+    }
+    Ok(())
+}
+
+pub(super) fn write_key<W: Write>(
+    out: &mut W,
+    key: &(Board, Player),
+) -> Result<(), CacheFileError> {
+    out.write_all(&encode_board(&key.0))?;
+    out.write_all(&[player_code(key.1)])?;
+    Ok(())
+}
+
+pub(super) fn read_key<R: Read>(input: &mut R) -> Result<(Board, Player), CacheFileError> {
+    let mut board = [0; BOARD_BYTES];
+    input.read_exact(&mut board)?;
+    let mut player = [0];
+    input.read_exact(&mut player)?;
+    Ok((decode_board(&board)?, player_from(player[0])?))
+}
+
+pub(super) fn write_value<W: Write>(
+    out: &mut W,
+    value: &(usize, End),
+) -> Result<(), CacheFileError> {
+    let end = match value.1 {
+        End::Stall => 0,
+        End::Win { player } => player_code(player),
+    };
+    out.write_all(&[value.0 as u8, end])?;
+    Ok(())
+}
+
+pub(super) fn read_value<R: Read>(input: &mut R) -> Result<(usize, End), CacheFileError> {
+    let mut value = [0; 2];
+    input.read_exact(&mut value)?;
+    let [column, end] = value;
+    let column = usize::from(column);
+    if column >= WIDTH {
+        return Err(CacheFileError::Format("invalid column"));
+    }
+    let end = match end {
+        0 => End::Stall,
+        code => End::Win {
+            player: player_from(code)?,
+        },
+    };
+    Ok((column, end))
+}
+
+/// Write the number of entries, then the entries
+pub(super) fn write_entries<W: Write>(
+    out: &mut W,
+    entries: &[Entry],
+) -> Result<(), CacheFileError> {
+    out.write_all(&(entries.len() as u64).to_le_bytes())?;
+    for (key, value) in entries {
+        write_key(out, key)?;
+        write_value(out, value)?;
+    }
+    Ok(())
+}
+
+pub(super) fn read_entries<R: Read>(input: &mut R) -> Result<Vec<Entry>, CacheFileError> {
+    let mut len = [0; 8];
+    input.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len) as usize;
+    // The length is not trusted to allocate
+    let mut entries = Vec::with_capacity(len.min(1 << 20));
+    for _ in 0..len {
+        entries.push((read_key(input)?, read_value(input)?));
+    }
+    Ok(entries)
+}
+
+/// Write `entries` into the file at `path`
+///
+/// The entries are written into a temporary file next to `path`, renamed over it once complete, so an
+/// interrupted save leaves the previous file intact.
+pub fn save(path: &Path, entries: &[Entry]) -> Result<(), CacheFileError> {
+    let temporary = temporary_path(path);
+    let written = write_file(&temporary, entries).and_then(|()| Ok(fs::rename(&temporary, path)?));
+    if written.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    written
+}
+
+/// Path of the file written by [`save`] before being renamed to `path`
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+fn write_file(path: &Path, entries: &[Entry]) -> Result<(), CacheFileError> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write_header(&mut out)?;
+    write_entries(&mut out, entries)?;
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(())
+}
+
+/// Read the entries of the file at `path`
+pub fn load(path: &Path) -> Result<Vec<Entry>, CacheFileError> {
+    let mut input = BufReader::new(File::open(path)?);
+    read_header(&mut input)?;
+    read_entries(&mut input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Game,
+        caches::{KnowledgeCache, KnowledgeCacheSharded},
+    };
+
+    #[test]
+    fn round_trip_and_rejected_header() {
+        let path = std::env::temp_dir().join(format!("cache-{}.p4kc", std::process::id()));
+        let cache = KnowledgeCacheSharded::default();
+        let mut game = Game::default();
+        for c in [4, 4, 3, 8, 0] {
+            game.play(c).unwrap();
+            let end = End::Win {
+                player: game.next_to_play(),
+            };
+            cache.remember(game.board(), game.next_to_play(), c, end);
+        }
+        cache.save(&path).unwrap();
+        assert!(!temporary_path(&path).exists());
+
+        let loaded = KnowledgeCacheSharded::default();
+        assert_eq!(loaded.load(&path).unwrap(), 5);
+        assert_eq!(loaded.stats().inserts, 0);
+        let mut expected = cache.entries();
+        let mut entries = loaded.entries();
+        expected.sort_by_key(|e| e.0);
+        entries.sort_by_key(|e| e.0);
+        assert_eq!(entries, expected);
+
+        // Another width
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[7] += 1;
+        std::fs::write(&path, bytes).unwrap();
+        assert!(matches!(
+            loaded.load(&path),
+            Err(CacheFileError::Dimensions { .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,439 @@
+//! A cache shared by several processes through a cache server
+//!
+//! The protocol starts with both sides sending the header of the cache files, so a client and a server
+//! built for different boards refuse each other. Then the client sends requests, made of a tag and a
+//! payload encoded like the cache files :
+//! - lookup : the number of positions and the positions, answered with a found flag and a value per position
+//! - store : the number of entries and the entries, not answered
+//! - len : answered with the number of entries
+//! - clear : not answered
+//! - entries : answered with the number of entries and the entries
+
+use std::{
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Write},
+    iter::once,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, Sender},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+use parking_lot::Mutex;
+
+use super::{
+    CacheFileError, CacheStats, Entry, KnowledgeCache,
+    persist::{
+        read_entries, read_header, read_key, read_value, write_entries, write_header, write_key,
+        write_value,
+    },
+    stats::Counters,
+};
+use crate::{End, Player, game::board::Board};
+
+const LOOKUP: u8 = 1;
+const STORE: u8 = 2;
+const LEN: u8 = 3;
+const CLEAR: u8 = 4;
+const ENTRIES: u8 = 5;
+
+/// Maximal number of entries sent in one store request
+const MAX_STORE_BATCH: usize = 1024;
+
+/// Time given to the server to connect or answer, after which the request fails
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Time without trying to connect again after a failed connection
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+fn read_u64<R: Read>(input: &mut R) -> Result<u64, CacheFileError> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// A connection to the cache server, after the exchange of the headers
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Connection {
+    /// Connect to the first of `addrs` reachable, every read and write failing after [`TIMEOUT`]
+    fn open(addrs: &[SocketAddr]) -> Result<Self, CacheFileError> {
+        let mut error = io::Error::new(ErrorKind::InvalidInput, "no address to connect to");
+        let stream = addrs
+            .iter()
+            .find_map(|addr| {
+                TcpStream::connect_timeout(addr, TIMEOUT)
+                    .inspect_err(|e| error = io::Error::new(e.kind(), e.to_string()))
+                    .ok()
+            })
+            .ok_or(error)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut connection = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        };
+        write_header(&mut connection.writer)?;
+        connection.writer.flush()?;
+        read_header(&mut connection.reader)?;
+        Ok(connection)
+    }
+
+    fn lookup(
+        &mut self,
+        keys: &[(Board, Player)],
+    ) -> Result<Vec<Option<(usize, End)>>, CacheFileError> {
+        self.writer.write_all(&[LOOKUP])?;
+        self.writer.write_all(&(keys.len() as u64).to_le_bytes())?;
+        for key in keys {
+            write_key(&mut self.writer, key)?;
+        }
+        self.writer.flush()?;
+        keys.iter()
+            .map(|_| {
+                let mut found = [0];
+                self.reader.read_exact(&mut found)?;
+                let value = read_value(&mut self.reader)?;
+                Ok((found[0] != 0).then_some(value))
+            })
+            .collect()
+    }
+
+    fn store(&mut self, entries: &[Entry]) -> Result<(), CacheFileError> {
+        self.writer.write_all(&[STORE])?;
+        write_entries(&mut self.writer, entries)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Send a request without payload
+    fn send(&mut self, tag: u8) -> Result<(), CacheFileError> {
+        self.writer.write_all(&[tag])?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// The address of the cache server, to open new connections
+struct Server {
+    addrs: Vec<SocketAddr>,
+    /// No connection is tried before this instant, after a failed one
+    retry_at: Mutex<Option<Instant>>,
+}
+
+impl Server {
+    /// A new connection, or `None` if the server can't be reached
+    fn open(&self) -> Option<Connection> {
+        if self.retry_at.lock().is_some_and(|t| Instant::now() < t) {
+            return None;
+        }
+        Connection::open(&self.addrs)
+            .inspect_err(|e| {
+                warn!("Can't reach the cache server, retrying in {RETRY_DELAY:?} : {e}");
+                *self.retry_at.lock() = Some(Instant::now() + RETRY_DELAY);
+            })
+            .ok()
+    }
+}
+
+/// Send the stores in batches, until the cache is dropped
+///
+/// After a failure, the connection is dropped and a new one is opened for the next batch.
+fn send_stores(server: Arc<Server>, connection: Connection, stores: Receiver<Entry>) {
+    let mut connection = Some(connection);
+    while let Ok(first) = stores.recv() {
+        let batch: Vec<_> = once(first)
+            .chain(stores.try_iter().take(MAX_STORE_BATCH - 1))
+            .collect();
+        if connection.is_none() {
+            connection = server.open();
+        }
+        let Some(c) = &mut connection else {
+            debug!("Dropping {} stores", batch.len());
+            continue;
+        };
+        debug!("Storing {} entries", batch.len());
+        if let Err(e) = c.store(&batch) {
+            warn!(
+                "Cache server store failed, {} entries dropped : {e}",
+                batch.len()
+            );
+            connection = None;
+        }
+    }
+}
+
+struct Remote {
+    server: Arc<Server>,
+    /// Connections for the requests waiting for an answer, not used by a request right now
+    idle: Mutex<Vec<Connection>>,
+    stores: Option<Sender<Entry>>,
+    writer: Option<JoinHandle<()>>,
+    stats: Counters,
+}
+
+impl Drop for Remote {
+    fn drop(&mut self) {
+        // Closing the channel stops the writer once the pending stores are sent
+        self.stores.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+#[derive(Clone)]
+/// A cache stored by a cache server (see the `cache_server` binary), shared by several processes
+///
+/// The stores are asynchronous : they are sent in batches by a background thread, so a position may be
+/// looked up before the server knows it. The threads making requests at the same time each use a
+/// connection of their own. A request not answered in time misses, like the ones made while the server
+/// can't be reached, and the stores are then dropped. A failed connection is replaced by a new one, at
+/// most once a second. The statistics are the ones of this client, where every store counts as an
+/// insert. Clones share the same connections.
+pub struct KnowledgeCacheRemote {
+    inner: Arc<Remote>,
+}
+
+impl KnowledgeCacheRemote {
+    /// Connect to the cache server at `addr`
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self, CacheFileError> {
+        let server = Arc::new(Server {
+            addrs: addr.to_socket_addrs()?.collect(),
+            retry_at: Mutex::new(None),
+        });
+        let requests = Connection::open(&server.addrs)?;
+        let store_connection = Connection::open(&server.addrs)?;
+        let (stores, receiver) = mpsc::channel();
+        let writer = {
+            let server = server.clone();
+            std::thread::spawn(move || send_stores(server, store_connection, receiver))
+        };
+        Ok(Self {
+            inner: Arc::new(Remote {
+                server,
+                idle: Mutex::new(vec![requests]),
+                stores: Some(stores),
+                writer: Some(writer),
+                stats: Counters::default(),
+            }),
+        })
+    }
+
+    /// Make a request on an idle connection, or else a new one, logging the failures
+    ///
+    /// The connection of a failed request is dropped, as an answer may still be on its way.
+    fn request<T>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> Result<T, CacheFileError>,
+    ) -> Option<T> {
+        let idle = self.inner.idle.lock().pop();
+        let mut connection = idle.or_else(|| self.inner.server.open())?;
+        match f(&mut connection) {
+            Ok(answer) => {
+                self.inner.idle.lock().push(connection);
+                Some(answer)
+            }
+            Err(e) => {
+                warn!("Cache server request failed : {e}");
+                None
+            }
+        }
+    }
+}
+
+impl KnowledgeCache for KnowledgeCacheRemote {
+    fn lookup(&self, board_state: Board, player: Player) -> Option<(usize, End)> {
+        self.lookup_batch(&[(board_state, player)])[0]
+    }
+
+    fn lookup_batch(&self, keys: &[(Board, Player)]) -> Vec<Option<(usize, End)>> {
+        if keys.is_empty() {
+            return Vec::new();
+        }
+        let found = self
+            .request(|c| c.lookup(keys))
+            .unwrap_or_else(|| vec![None; keys.len()]);
+        found
+            .into_iter()
+            .map(|f| self.inner.stats.lookup(f))
+            .collect()
+    }
+
+    fn remember(
+        &self,
+        board_state: Board,
+        player: Player,
+        best_choice: usize,
+        projected_ending: End,
+    ) {
+        self.inner.stats.insert(false);
+        if let Some(stores) = &self.inner.stores {
+            // The writer only stops once the cache is dropped
+            let _ = stores.send(((board_state, player), (best_choice, projected_ending)));
+        }
+    }
+
+    fn restore(&self, entries: &[Entry]) {
+        if let Some(stores) = &self.inner.stores {
+            for &entry in entries {
+                let _ = stores.send(entry);
+            }
+        }
+    }
+
+    fn clean(&mut self) {
+        self.request(|c| c.send(CLEAR));
+    }
+
+    fn len(&self) -> usize {
+        self.request(|c| {
+            c.send(LEN)?;
+            read_u64(&mut c.reader)
+        })
+        .map_or(0, |n| n as usize)
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.inner.stats.snapshot()
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        self.request(|c| {
+            c.send(ENTRIES)?;
+            read_entries(&mut c.reader)
+        })
+        .unwrap_or_default()
+    }
+}
+
+/// Answer the requests of a client of the cache server, until it disconnects
+///
+/// `cache` should share its entries between its clones, each client being served with its own clone.
+pub fn serve_client<C: KnowledgeCache>(
+    stream: TcpStream,
+    cache: &mut C,
+) -> Result<(), CacheFileError> {
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    read_header(&mut reader)?;
+    write_header(&mut writer)?;
+    writer.flush()?;
+
+    loop {
+        let mut tag = [0];
+        match reader.read_exact(&mut tag) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            r => r?,
+        }
+        match tag[0] {
+            LOOKUP => {
+                let len = read_u64(&mut reader)?;
+                let mut keys = Vec::with_capacity((len as usize).min(MAX_STORE_BATCH));
+                for _ in 0..len {
+                    keys.push(read_key(&mut reader)?);
+                }
+                for found in cache.lookup_batch(&keys) {
+                    writer.write_all(&[found.is_some() as u8])?;
+                    write_value(&mut writer, &found.unwrap_or((0, End::Stall)))?;
+                }
+            }
+            STORE => {
+                for ((board, player), (column, end)) in read_entries(&mut reader)? {
+                    cache.remember(board, player, column, end);
+                }
+            }
+            LEN => writer.write_all(&(cache.len() as u64).to_le_bytes())?,
+            CLEAR => cache.clean(),
+            ENTRIES => write_entries(&mut writer, &cache.entries())?,
+            _ => return Err(CacheFileError::Format("unknown request")),
+        }
+        writer.flush()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Game, caches::KnowledgeCacheSharded};
+    use std::net::TcpListener;
+
+    #[test]
+    fn clients_share_the_server_cache() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let cache = KnowledgeCacheSharded::default();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut cache = cache.clone();
+                std::thread::spawn(move || serve_client(stream.unwrap(), &mut cache));
+            }
+        });
+
+        let mut game = Game::default();
+        game.play(4).unwrap();
+        let key = (game.board(), Player::SECOND);
+        let first = KnowledgeCacheRemote::connect(addr).unwrap();
+        first.remember(key.0, key.1, 3, End::Stall);
+
+        let second = KnowledgeCacheRemote::connect(addr).unwrap();
+        let mut found = None;
+        for _ in 0..100 {
+            found = second.lookup_batch(&[key, (game.board(), Player::FIRST)])[0];
+            if found.is_some() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(found, Some((3, End::Stall)));
+        assert_eq!(second.len(), 1);
+        assert_eq!(second.entries(), vec![(key, (3, End::Stall))]);
+    }
+
+    #[test]
+    fn a_request_not_answered_in_time_misses() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let cache = KnowledgeCacheSharded::default();
+        std::thread::spawn(move || {
+            let mut streams = listener.incoming();
+            // The first connection never answers the requests
+            let mut silent = streams.next().unwrap().unwrap();
+            read_header(&mut silent).unwrap();
+            write_header(&mut silent).unwrap();
+            for stream in streams {
+                let mut cache = cache.clone();
+                std::thread::spawn(move || serve_client(stream.unwrap(), &mut cache));
+            }
+            drop(silent);
+        });
+
+        let mut game = Game::default();
+        game.play(4).unwrap();
+        let key = (game.board(), Player::SECOND);
+        let remote = KnowledgeCacheRemote::connect(addr).unwrap();
+        let start = Instant::now();
+        assert_eq!(remote.lookup(key.0, key.1), None);
+        assert!(start.elapsed() < TIMEOUT * 4);
+
+        // The request connection is replaced
+        remote.remember(key.0, key.1, 3, End::Stall);
+        let mut found = None;
+        for _ in 0..100 {
+            found = remote.lookup(key.0, key.1);
+            if found.is_some() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(found, Some((3, End::Stall)));
+    }
+}
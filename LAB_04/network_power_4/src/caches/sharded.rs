@@ -0,0 +1,143 @@
+use parking_lot::RwLock;
+use std::{collections::BTreeMap, sync::Arc};
+
+use super::{CacheStats, Entry, KnowledgeCache, stats::Counters};
+use crate::{End, Player, game::board::Board};
+
+/// Default number of shards, enough to keep the collisions between threads rare
+const DEFAULT_SHARDS: usize = 64;
+
+type Shard = RwLock<BTreeMap<(Board, Player), (usize, End)>>;
+
+#[derive(Debug, Clone)]
+/// A thread safe cache split into independently locked shards
+///
+/// Each position is stored in the shard picked by its hash, so threads working on different positions
+/// rarely wait on the same lock. Clones share the same entries.
+pub struct KnowledgeCacheSharded {
+    shards: Arc<[Shard]>,
+    stats: Arc<Counters>,
+}
+
+impl KnowledgeCacheSharded {
+    /// A cache split into `shards` shards (rounded up to a power of two)
+    pub fn with_shards(shards: usize) -> Self {
+        let n = shards.max(1).next_power_of_two();
+        Self {
+            shards: (0..n).map(|_| RwLock::new(BTreeMap::new())).collect(),
+            stats: Arc::default(),
+        }
+    }
+
+    fn shard(&self, board_state: &Board, player: Player) -> &Shard {
+        let hash = board_state.fingerprint() ^ player as u64;
+        // The low bits of a FNV hash only depend on the low bits of the cells, so the hash is mixed by a
+        // Fibonacci multiplication, whose top bits depend on all the bits of the hash
+        let mixed = hash.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let bits = self.shards.len().trailing_zeros();
+        &self.shards[mixed.checked_shr(64 - bits).unwrap_or(0) as usize]
+    }
+}
+
+impl Default for KnowledgeCacheSharded {
+    fn default() -> Self {
+        Self::with_shards(DEFAULT_SHARDS)
+    }
+}
+
+impl KnowledgeCache for KnowledgeCacheSharded {
+    fn lookup(&self, board_state: Board, player: Player) -> Option<(usize, End)> {
+        let found = self
+            .shard(&board_state, player)
+            .read()
+            .get(&(board_state, player))
+            .copied();
+        self.stats.lookup(found)
+    }
+
+    fn remember(
+        &self,
+        board_state: Board,
+        player: Player,
+        best_choice: usize,
+        projected_ending: End,
+    ) {
+        let old = self
+            .shard(&board_state, player)
+            .write()
+            .insert((board_state, player), (best_choice, projected_ending));
+        self.stats.insert(old.is_some());
+    }
+
+    fn restore(&self, entries: &[Entry]) {
+        for &(key, value) in entries {
+            self.shard(&key.0, key.1).write().insert(key, value);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.read().len()).sum()
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        self.shards
+            .iter()
+            .flat_map(|s| s.read().iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>())
+            .collect()
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.stats.snapshot()
+    }
+
+    fn clean(&mut self) {
+        for shard in self.shards.iter() {
+            shard.write().clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Game;
+
+    #[test]
+    fn entries_spread_over_the_shards() {
+        let cache = KnowledgeCacheSharded::with_shards(4);
+        let mut game = Game::default();
+        for c in [3, 3, 4, 2, 5, 0, 1, 6] {
+            game.play(c).unwrap();
+            cache.remember(game.board(), Player::FIRST, c, End::Stall);
+        }
+        assert_eq!(cache.len(), 8);
+        assert!(cache.shards.iter().filter(|s| !s.read().is_empty()).count() > 1);
+        assert_eq!(
+            cache.lookup(game.board(), Player::FIRST),
+            Some((6, End::Stall))
+        );
+        assert_eq!(cache.lookup(game.board(), Player::SECOND), None);
+
+        // All the positions of the first three plies fill every shard, or the only one
+        for shards in [1, 16] {
+            let cache = KnowledgeCacheSharded::with_shards(shards);
+            let mut boards = vec![Game::default().board()];
+            for ply in 0..3 {
+                let player = if ply % 2 == 0 {
+                    Player::FIRST
+                } else {
+                    Player::SECOND
+                };
+                boards = boards
+                    .iter()
+                    .flat_map(|b| b.legal_moves(player))
+                    .map(|(_, b, _)| b)
+                    .collect();
+                for b in &boards {
+                    cache.remember(*b, player.other(), 0, End::Stall);
+                }
+            }
+            assert!(cache.shards.iter().all(|s| !s.read().is_empty()));
+        }
+    }
+}
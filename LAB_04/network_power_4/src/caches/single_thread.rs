@@ -1,17 +1,19 @@
 use crate::{End, Player, game::board::Board};
 use std::{cell::RefCell, collections::BTreeMap};
 
-use super::KnowledgeCache;
+use super::{CacheStats, Entry, KnowledgeCache, stats::Counters};
 
 #[derive(Debug, Default, Clone)]
 /// A non-thread safe implementation of cache
 pub struct KnowledgeCacheSingleThread {
     inner: RefCell<BTreeMap<(Board, Player), (usize, End)>>,
+    stats: Counters,
 }
 
 impl KnowledgeCache for KnowledgeCacheSingleThread {
     fn lookup(&self, board_state: Board, player: Player) -> Option<(usize, End)> {
-        self.inner.borrow().get(&(board_state, player)).copied()
+        self.stats
+            .lookup(self.inner.borrow().get(&(board_state, player)).copied())
     }
     fn remember(
         &self,
@@ -20,9 +22,14 @@ impl KnowledgeCache for KnowledgeCacheSingleThread {
         best_choice: usize,
         projected_ending: End,
     ) {
-        self.inner
+        let old = self
+            .inner
             .borrow_mut()
             .insert((board_state, player), (best_choice, projected_ending));
+        self.stats.insert(old.is_some());
+    }
+    fn restore(&self, entries: &[Entry]) {
+        self.inner.borrow_mut().extend(entries.iter().copied());
     }
     fn clean(&mut self) {
         self.inner.get_mut().clear();
@@ -30,4 +37,10 @@ impl KnowledgeCache for KnowledgeCacheSingleThread {
     fn len(&self) -> usize {
         self.inner.borrow().len()
     }
+    fn stats(&self) -> CacheStats {
+        self.stats.snapshot()
+    }
+    fn entries(&self) -> Vec<Entry> {
+        self.inner.borrow().iter().map(|(k, v)| (*k, *v)).collect()
+    }
 }
@@ -0,0 +1,151 @@
+use std::fmt::Display;
+
+use crate::sync::{AtomicU64, Ordering};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Counters of the operations on a cache, since its creation
+pub struct CacheStats {
+    pub lookups: u64,
+    /// Lookups finding an entry
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries added for a new position
+    pub inserts: u64,
+    /// Entries replaced for an already known position
+    pub overwrites: u64,
+    /// Entries removed to make room for new ones
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    /// Proportion of the lookups finding an entry
+    pub fn hit_rate(&self) -> f64 {
+        if self.lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.lookups as f64
+        }
+    }
+}
+
+impl Display for CacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} lookups ({:.1}% hits), {} inserts, {} overwrites, {} evictions",
+            self.lookups,
+            100.0 * self.hit_rate(),
+            self.inserts,
+            self.overwrites,
+            self.evictions
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+/// The counters behind [`CacheStats`], cheap to update from several threads
+///
+/// Each counter is independent, so they are only updated with relaxed atomics.
+pub(crate) struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    inserts: AtomicU64,
+    overwrites: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl Counters {
+    pub fn lookup<T>(&self, found: Option<T>) -> Option<T> {
+        let counter = match found {
+            Some(_) => &self.hits,
+            None => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    /// Count a `remember`, `replaced` telling if the position was already known
+    pub fn insert(&self, replaced: bool) {
+        let counter = match replaced {
+            true => &self.overwrites,
+            false => &self.inserts,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn evict(&self) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> CacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        CacheStats {
+            lookups: hits + misses,
+            hits,
+            misses,
+            inserts: self.inserts.load(Ordering::Relaxed),
+            overwrites: self.overwrites.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Clone for Counters {
+    fn clone(&self) -> Self {
+        let stats = self.snapshot();
+        Self {
+            hits: stats.hits.into(),
+            misses: stats.misses.into(),
+            inserts: stats.inserts.into(),
+            overwrites: stats.overwrites.into(),
+            evictions: stats.evictions.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        End, Game, Player,
+        caches::{
+            KnowledgeCache, KnowledgeCacheBounded, KnowledgeCacheMultiThread,
+            KnowledgeCacheSharded, KnowledgeCacheSingleThread,
+        },
+    };
+
+    fn count_the_operations<C: KnowledgeCache>(cache: C) {
+        let mut game = Game::default();
+        let first = (game.board(), Player::FIRST);
+        game.play(4).unwrap();
+        let second = (game.board(), Player::SECOND);
+
+        assert_eq!(cache.lookup(first.0, first.1), None);
+        cache.remember(first.0, first.1, 4, End::Stall);
+        cache.remember(first.0, first.1, 3, End::Stall);
+        assert_eq!(cache.lookup(first.0, first.1), Some((3, End::Stall)));
+        let expected = CacheStats {
+            lookups: 2,
+            hits: 1,
+            misses: 1,
+            inserts: 1,
+            overwrites: 1,
+            evictions: 0,
+        };
+        assert_eq!(cache.stats(), expected);
+
+        // The restored entries are stored, but not counted
+        cache.restore(&[(first, (4, End::Stall)), (second, (5, End::Stall))]);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats(), expected);
+    }
+
+    #[test]
+    fn every_cache_counts_its_operations() {
+        count_the_operations(KnowledgeCacheSingleThread::default());
+        count_the_operations(KnowledgeCacheMultiThread::default());
+        count_the_operations(KnowledgeCacheSharded::default());
+        count_the_operations(KnowledgeCacheBounded::with_capacity(4));
+    }
+}
@@ -1,18 +1,46 @@
 //! Evaluator logic
 
-use std::{ops::Neg, sync::Arc};
+use std::{ops::Neg, pin::pin, sync::Arc, time::Duration};
+
+use futures::{
+    FutureExt, Stream, StreamExt,
+    future::{Either, LocalBoxFuture, select},
+    stream::{self, LocalBoxStream},
+};
+
+use rand::{SeedableRng, rngs::StdRng, seq::IndexedRandom};
 
 use crate::{
     Game, Play, Player,
+    executor::sleep,
     game::{End, board::Board},
 };
 
+mod alpha_beta;
+pub mod async_min_max;
 pub mod async_wrapper;
+pub mod ensemble;
+pub mod lazy_smp;
 pub mod min_max;
 pub mod min_max_cached;
+pub mod parallel_min_max;
+pub mod portfolio;
 pub mod random_ai;
+pub mod random_opening;
+mod stop;
 pub mod threaded_wrapper;
 
+/// A [`SyncEvaluator`] that can be shared between threads
+pub(crate) type SharedEvaluator = Arc<dyn SyncEvaluator + Send + Sync>;
+
+/// Build the random generator used to take a decision on `board`.
+///
+/// The stream only depends on the `seed` and the position, so a replayed game takes exactly the same
+/// decisions, whatever the order (or the thread) in which positions are evaluated.
+pub(crate) fn position_rng(seed: u64, board: &Board) -> StdRng {
+    StdRng::seed_from_u64(seed ^ board.fingerprint())
+}
+
 #[derive(Debug, Clone, Copy)]
 /// Estimation of the ending from a position.
 pub enum EstimationResult {
@@ -68,6 +96,28 @@ impl EstimationResult {
             Player::SECOND => *inputs.iter().min_by_key(|e| e.1).unwrap(),
         }
     }
+    /// Get the best move from a list for a given player, breaking ties at random with `rng`.
+    ///
+    /// ```
+    /// use network_power_4::{EstimationResult, Player};
+    /// use rand::{SeedableRng, rngs::StdRng};
+    /// let moves = [
+    ///     (0, EstimationResult::Partial(1.0)),
+    ///     (1, EstimationResult::Partial(-2.0)),
+    ///     (5, EstimationResult::Partial(1.0))];
+    /// let mut rng = StdRng::seed_from_u64(42);
+    /// let best = EstimationResult::best_for_with_rng(&moves[..], Player::FIRST, &mut rng).0;
+    /// assert!(best == 0 || best == 5);
+    /// ```
+    pub fn best_for_with_rng<T: Copy, R: rand::Rng + ?Sized>(
+        inputs: &[(T, EstimationResult)],
+        player: Player,
+        rng: &mut R,
+    ) -> (T, EstimationResult) {
+        let best = Self::best_for(inputs, player).1;
+        let ties: Vec<_> = inputs.iter().filter(|e| e.1 == best).collect();
+        **ties.choose(rng).unwrap()
+    }
     pub fn into_partial(&self) -> Self {
         match *self {
             EstimationResult::Partial(_) => *self,
@@ -159,6 +209,12 @@ pub trait SyncEvaluator {
     }
 }
 
+impl<T: SyncEvaluator + ?Sized> SyncEvaluator for Box<T> {
+    fn evaluate(&self, board: &Board, player: Player) -> (Play, EstimationResult) {
+        (**self).evaluate(board, player)
+    }
+}
+
 /// Evaluator that can give a recomendation for a play for a state of the game
 ///
 /// This one has a async interface
@@ -174,8 +230,106 @@ pub trait AsyncEvaluator {
     fn evaluate_game(&self, game: &Game) -> impl Future<Output = (Play, EstimationResult)> {
         self.evaluate(Arc::from(game.board()), game.next_to_play())
     }
+
+    /// Return the estimated best plays for the player `player` in the state `board`, better and better
+    ///
+    /// The last one is the result of [`AsyncEvaluator::evaluate`]. By default, it is the only one.
+    fn evaluate_stream(
+        &self,
+        board: Arc<Board>,
+        player: Player,
+    ) -> impl Stream<Item = (Play, EstimationResult)> {
+        stream::once(self.evaluate(board, player))
+    }
+
+    /// Return the estimated best plays for the game state, better and better
+    fn evaluate_game_stream(&self, game: &Game) -> impl Stream<Item = (Play, EstimationResult)> {
+        self.evaluate_stream(Arc::from(game.board()), game.next_to_play())
+    }
+}
+
+/// The last result given by `results` within `budget`, or else its first one
+///
+/// The first result is awaited whatever the budget, so it should come quickly, like the shallowest
+/// search of an iterative deepening. The stream is dropped on return, which cancels the searches still
+/// running. Returns `None` if the stream is empty.
+pub async fn best_in_time<S>(results: S, budget: Duration) -> Option<(Play, EstimationResult)>
+where
+    S: Stream<Item = (Play, EstimationResult)>,
+{
+    let mut results = pin!(results);
+    let mut deadline = sleep(budget);
+    let mut best = results.next().await?;
+    loop {
+        match select(results.next(), &mut deadline).await {
+            Either::Left((Some(result), _)) => best = result,
+            Either::Left((None, _)) | Either::Right(_) => return Some(best),
+        }
+    }
+}
+
+/// An [`AsyncEvaluator`] usable as a trait object, see [`BoxedAsyncEvaluator`]
+///
+/// It is implemented by every [`AsyncEvaluator`], whose future is boxed. The future is not `Send`, as
+/// the one of an [`AsyncEvaluator`] may not be.
+pub trait DynAsyncEvaluator {
+    /// Return the estimated best play for the player `player` in the state `board`
+    fn evaluate_boxed(
+        &self,
+        board: Arc<Board>,
+        player: Player,
+    ) -> LocalBoxFuture<'_, (Play, EstimationResult)>;
+
+    /// Return the estimated best plays for the player `player` in the state `board`, better and better
+    fn evaluate_stream_boxed(
+        &self,
+        board: Arc<Board>,
+        player: Player,
+    ) -> LocalBoxStream<'_, (Play, EstimationResult)>;
+}
+
+impl<T: AsyncEvaluator> DynAsyncEvaluator for T {
+    fn evaluate_boxed(
+        &self,
+        board: Arc<Board>,
+        player: Player,
+    ) -> LocalBoxFuture<'_, (Play, EstimationResult)> {
+        self.evaluate(board, player).boxed_local()
+    }
+
+    fn evaluate_stream_boxed(
+        &self,
+        board: Arc<Board>,
+        player: Player,
+    ) -> LocalBoxStream<'_, (Play, EstimationResult)> {
+        self.evaluate_stream(board, player).boxed_local()
+    }
 }
 
+impl AsyncEvaluator for BoxedAsyncEvaluator {
+    fn evaluate(
+        &self,
+        board: Arc<Board>,
+        player: Player,
+    ) -> impl Future<Output = (Play, EstimationResult)> {
+        (**self).evaluate_boxed(board, player)
+    }
+
+    fn evaluate_stream(
+        &self,
+        board: Arc<Board>,
+        player: Player,
+    ) -> impl Stream<Item = (Play, EstimationResult)> {
+        (**self).evaluate_stream_boxed(board, player)
+    }
+}
+
+/// A [`SyncEvaluator`] chosen at runtime
+pub type BoxedSyncEvaluator = Box<dyn SyncEvaluator + Send + Sync>;
+
+/// An [`AsyncEvaluator`] chosen at runtime
+pub type BoxedAsyncEvaluator = Box<dyn DynAsyncEvaluator + Send + Sync>;
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -196,7 +350,7 @@ mod tests {
     fn is_better_eq_comp() {
         for a in ESTIMATIONS {
             for b in ESTIMATIONS {
-                assert_eq!((&a).is_better(&b, &Player::FIRST), a < b);
+                assert_eq!(a.is_better(&b, &Player::FIRST), a < b);
             }
         }
     }
@@ -0,0 +1,241 @@
+//! Building blocks of the alpha-beta searches
+//!
+//! Scores are always seen from the `FIRST` player point of view : `FIRST` maximises and `SECOND` minimises.
+//! The caches are keyed by the position and the player to move, and only store proven endings.
+
+use crate::{End, Player, WIDTH, caches::KnowledgeCache, game::board::Board, heuristic::Heuristic};
+
+use rand::seq::SliceRandom;
+
+use super::{EstimationResult, position_rng, stop::stopped};
+
+#[derive(Debug, Clone, Copy)]
+/// Result of the search of a position
+pub(crate) struct Outcome {
+    /// Best column to play
+    pub column: usize,
+    pub value: EstimationResult,
+    /// The value is the exact ending of the game, whatever the depth of the search
+    pub proven: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// The alpha-beta window : only the values strictly between `alpha` and `beta` can change the result
+pub(crate) struct Window {
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Window {
+    pub fn full() -> Self {
+        Self {
+            alpha: f64::NEG_INFINITY,
+            beta: f64::INFINITY,
+        }
+    }
+    /// The opponent of `player` will never allow `value`
+    pub fn cuts(&self, value: EstimationResult, player: Player) -> bool {
+        let v: f64 = value.into();
+        match player {
+            Player::FIRST => v >= self.beta,
+            Player::SECOND => v <= self.alpha,
+        }
+    }
+    /// `player` is guaranteed to get at least `value`
+    pub fn tighten(&mut self, value: EstimationResult, player: Player) {
+        let v: f64 = value.into();
+        match player {
+            Player::FIRST => self.alpha = self.alpha.max(v),
+            Player::SECOND => self.beta = self.beta.min(v),
+        }
+    }
+}
+
+/// The legal moves, the center columns first as they are usually the best
+pub(crate) fn ordered_moves(board: &Board, player: Player) -> Vec<(usize, Board, Option<End>)> {
+    let mut moves = board.legal_moves(player);
+    moves.sort_by_key(|(idx, _, _)| idx.abs_diff(WIDTH / 2));
+    moves
+}
+
+/// The legal moves of the root, in an order drawn from `seed` if any
+///
+/// The search keeps the first of the equally good moves, so the seeded order breaks the ties at
+/// random, reproducibly from the seed and the position.
+pub(crate) fn root_moves(
+    board: &Board,
+    player: Player,
+    seed: Option<u64>,
+) -> Vec<(usize, Board, Option<End>)> {
+    let mut moves = ordered_moves(board, player);
+    if let Some(seed) = seed {
+        moves.shuffle(&mut position_rng(seed, board));
+    }
+    moves
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Best outcome among the children of a position
+pub(crate) struct Best {
+    player: Player,
+    outcome: Option<Outcome>,
+    all_proven: bool,
+    cutoff: bool,
+}
+
+impl Best {
+    pub fn new(player: Player) -> Self {
+        Self {
+            player,
+            outcome: None,
+            all_proven: true,
+            cutoff: false,
+        }
+    }
+    /// Take into account the outcome of the child reached by playing `column`.
+    ///
+    /// Return `true` if the search of the other children can be stopped.
+    pub fn record(&mut self, column: usize, child: Outcome, window: &mut Window) -> bool {
+        self.all_proven &= child.proven;
+        let child = Outcome { column, ..child };
+        if self
+            .outcome
+            .is_none_or(|b| b.value.is_better(&child.value, &self.player))
+        {
+            self.outcome = Some(child);
+        }
+        if window.cuts(child.value, self.player) {
+            self.cutoff = true;
+        }
+        window.tighten(child.value, self.player);
+        self.cutoff
+    }
+    /// Outcome of the position
+    pub fn finish(&self) -> Outcome {
+        let best = self
+            .outcome
+            .expect("A position without legal moves has an ending");
+        let win = best.value
+            == EstimationResult::Full(End::Win {
+                player: self.player,
+            });
+        Outcome {
+            proven: (win && best.proven) || (self.all_proven && !self.cutoff),
+            ..best
+        }
+    }
+}
+
+/// A sequential alpha-beta search
+pub(crate) struct Searcher<'a, C, H, S> {
+    pub cache: &'a C,
+    pub heuristic: &'a H,
+    /// Checked at each node, the search returns `None` as soon as it is `true`, or the evaluation is
+    /// [`stopped`]
+    pub stop: S,
+}
+
+impl<C: KnowledgeCache, H: Heuristic, S: Fn() -> bool> Searcher<'_, C, H, S> {
+    /// Search `board` with `player` to move, up to `depth` plies, its moves in the order of
+    /// [`root_moves`]
+    pub fn search(
+        &self,
+        board: &Board,
+        player: Player,
+        depth: usize,
+        window: Window,
+        seed: Option<u64>,
+    ) -> Option<Outcome> {
+        let known = self.cache.lookup(*board, player);
+        if known.is_some() || depth == 0 || (self.stop)() || stopped() {
+            return self.search_known(board, player, depth, window, known);
+        }
+        self.search_moves(
+            board,
+            player,
+            depth,
+            window,
+            root_moves(board, player, seed),
+        )
+    }
+
+    /// Search `board`, whose entry in the cache is `known`
+    fn search_known(
+        &self,
+        board: &Board,
+        player: Player,
+        depth: usize,
+        window: Window,
+        known: Option<(usize, End)>,
+    ) -> Option<Outcome> {
+        if (self.stop)() || stopped() {
+            return None;
+        }
+        if let Some((column, end)) = known {
+            return Some(Outcome {
+                column,
+                value: EstimationResult::Full(end),
+                proven: true,
+            });
+        }
+        if depth == 0 {
+            return Some(Outcome {
+                column: WIDTH / 2,
+                value: EstimationResult::Partial(self.heuristic.eval(board)),
+                proven: false,
+            });
+        }
+        self.search_moves(board, player, depth, window, ordered_moves(board, player))
+    }
+
+    /// Search the children of `board`, in the order of `moves`
+    ///
+    /// The children are looked up in the cache all at once.
+    pub fn search_moves(
+        &self,
+        board: &Board,
+        player: Player,
+        depth: usize,
+        window: Window,
+        moves: Vec<(usize, Board, Option<End>)>,
+    ) -> Option<Outcome> {
+        let mut window = window;
+        let mut best = Best::new(player);
+        let unknown: Vec<_> = moves
+            .iter()
+            .filter(|(_, _, e)| e.is_none())
+            .map(|(_, b, _)| (*b, player.other()))
+            .collect();
+        let mut known = self.cache.lookup_batch(&unknown).into_iter();
+        for (idx, b, e) in moves {
+            let child = match e {
+                Some(e) => Outcome {
+                    column: idx,
+                    value: EstimationResult::Full(e),
+                    proven: true,
+                },
+                None => {
+                    let known = known.next().flatten();
+                    self.search_known(&b, player.other(), depth - 1, window, known)?
+                }
+            };
+            if best.record(idx, child, &mut window) {
+                break;
+            }
+        }
+        Some(remember(self.cache, board, player, best.finish()))
+    }
+}
+
+/// Store the outcome in the cache if it is proven
+pub(crate) fn remember<C: KnowledgeCache>(
+    cache: &C,
+    board: &Board,
+    player: Player,
+    outcome: Outcome,
+) -> Outcome {
+    if let (true, EstimationResult::Full(end)) = (outcome.proven, outcome.value) {
+        cache.remember(*board, player, outcome.column, end);
+    }
+    outcome
+}
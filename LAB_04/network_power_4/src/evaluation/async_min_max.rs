@@ -0,0 +1,299 @@
+use std::{
+    panic,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+};
+
+use futures::{
+    FutureExt, Stream, StreamExt,
+    future::{BoxFuture, join, join_all},
+    stream,
+};
+
+use crate::{
+    Play, Player,
+    game::board::Board,
+    heuristic::{Heuristic, NaiveHeuristic},
+    thread_pool::{JoinError, ThreadPool},
+};
+
+use super::{
+    AsyncEvaluator, EstimationResult,
+    async_wrapper::POOL,
+    min_max::MinMaxPolicy,
+    stop::{self, StopOnDrop},
+};
+
+/// A min-max search that is itself async, its sub-searches being futures instead of blocked threads
+///
+/// A node with at least `spawn_depth` plies left searches its children at the same time while a permit
+/// is free, or else one after the other. The nodes below are searched by a plain [`MinMaxPolicy`], as a
+/// job of the pool, so the runtime is never blocked. No task is spawned : the evaluation only needs to
+/// be polled, by tokio as well as by [`crate::executor::block_on`].
+///
+/// Like [`MinMaxPolicy`], the last best column is played. Dropping the evaluation cancels the leaf
+/// searches not started yet, and stops the others.
+pub struct AsyncMinMax<H = NaiveHeuristic> {
+    max_depth: usize,
+    shared: Shared<H>,
+}
+
+struct Shared<H> {
+    spawn_depth: usize,
+    /// The sub-searches running at the same time as their siblings, each one holds a permit
+    permits: Arc<Permits>,
+    leaves: Arc<MinMaxPolicy<H>>,
+    /// Pool running the leaf searches, a shared one if `None`
+    pool: Option<Arc<ThreadPool>>,
+}
+
+impl<H> Clone for Shared<H> {
+    fn clone(&self) -> Self {
+        Self {
+            spawn_depth: self.spawn_depth,
+            permits: self.permits.clone(),
+            leaves: self.leaves.clone(),
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+/// A count of free permits, which does not depend on the runtime
+struct Permits(AtomicUsize);
+
+/// A permit taken from [`Permits`], given back when dropped
+struct Permit(Arc<Permits>);
+
+impl Permits {
+    fn try_acquire(self: &Arc<Self>) -> Option<Permit> {
+        self.0
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |n| n.checked_sub(1))
+            .ok()
+            .map(|_| Permit(self.clone()))
+    }
+
+    #[cfg(test)]
+    fn available(&self) -> usize {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.0.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl AsyncMinMax {
+    pub fn new(max_depth: usize) -> Self {
+        Self::with_heuristic(max_depth, NaiveHeuristic)
+    }
+}
+
+impl<H: Heuristic> AsyncMinMax<H> {
+    /// A search evaluating the leaves with `heuristic`, with a sub-search per core at most
+    pub fn with_heuristic(max_depth: usize, heuristic: H) -> Self {
+        let tasks = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self {
+            max_depth,
+            shared: Shared {
+                spawn_depth: 3,
+                permits: Arc::new(Permits(AtomicUsize::new(tasks))),
+                leaves: Arc::new(MinMaxPolicy::with_heuristic(0, heuristic)),
+                pool: None,
+            },
+        }
+    }
+
+    /// Only split the nodes with at least `spawn_depth` plies left to search
+    pub fn with_spawn_depth(mut self, spawn_depth: usize) -> Self {
+        self.shared.spawn_depth = spawn_depth.max(1);
+        self
+    }
+
+    /// Run `tasks` sub-searches at most at the same time as their siblings
+    pub fn with_max_tasks(mut self, tasks: usize) -> Self {
+        self.shared.permits = Arc::new(Permits(AtomicUsize::new(tasks)));
+        self
+    }
+
+    /// Run the leaf searches on `pool` instead of the shared one
+    pub fn with_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.shared.pool = Some(pool);
+        self
+    }
+}
+
+impl<H> Clone for AsyncMinMax<H> {
+    fn clone(&self) -> Self {
+        Self {
+            max_depth: self.max_depth,
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+/// The result of a job of the pool, whose panic is given back to the caller
+fn resume<T>(result: Result<T, JoinError>) -> T {
+    match result {
+        Ok(result) => result,
+        Err(JoinError::Panic(payload)) => panic::resume_unwind(payload),
+        Err(e) => panic!("The leaf search was cancelled : {e}"),
+    }
+}
+
+impl<H: Heuristic + Send + Sync + 'static> Shared<H> {
+    /// Best column for `player` on `board` and its value, searching `depth` plies
+    fn search(
+        &self,
+        board: Board,
+        player: Player,
+        depth: usize,
+    ) -> BoxFuture<'static, (usize, EstimationResult)> {
+        let shared = self.clone();
+        async move {
+            if depth < shared.spawn_depth {
+                // Stopped if the evaluation is dropped once the job has started
+                let stop = StopOnDrop(Arc::new(AtomicBool::new(false)));
+                let flag = Some(stop.0.clone());
+                let leaves = shared.leaves.clone();
+                let job =
+                    shared.pool.as_deref().unwrap_or(&POOL).execute(move || {
+                        stop::stoppable(flag, || leaves.max(&board, player, depth))
+                    });
+                return resume(job.await);
+            }
+            let mut results = Vec::new();
+            let mut concurrent = Vec::new();
+            let mut sequential = Vec::new();
+            for (rank, (idx, b, e)) in board.legal_moves(player).into_iter().enumerate() {
+                match (e, shared.permits.try_acquire()) {
+                    (Some(e), _) => results.push((rank, (idx, EstimationResult::Full(e)))),
+                    (None, Some(permit)) => {
+                        let child = shared.search(b, player.other(), depth - 1);
+                        concurrent.push(async move {
+                            let (_, value) = child.await;
+                            drop(permit);
+                            (rank, (idx, value))
+                        });
+                    }
+                    (None, None) => sequential.push((rank, idx, b)),
+                }
+            }
+            // No permit left, these children are searched one after the other
+            let sequential = async {
+                let mut results = Vec::new();
+                for (rank, idx, b) in sequential {
+                    let (_, value) = shared.search(b, player.other(), depth - 1).await;
+                    results.push((rank, (idx, value)));
+                }
+                results
+            };
+            let (concurrent, sequential) = join(join_all(concurrent), sequential).await;
+            results.extend(concurrent);
+            results.extend(sequential);
+            // In the order of the moves, which breaks the ties
+            results.sort_by_key(|(rank, _)| *rank);
+            let results: Vec<_> = results.into_iter().map(|(_, result)| result).collect();
+            EstimationResult::best_for(&results, player)
+        }
+        .boxed()
+    }
+}
+
+impl<H: Heuristic + Send + Sync + 'static> AsyncEvaluator for AsyncMinMax<H> {
+    async fn evaluate(&self, board: Arc<Board>, player: Player) -> (Play, EstimationResult) {
+        let (column, value) = self.shared.search(*board, player, self.max_depth).await;
+        (Play::try_from((column, player)).unwrap(), value)
+    }
+
+    /// Search deeper and deeper, up to the maximal depth
+    fn evaluate_stream(
+        &self,
+        board: Arc<Board>,
+        player: Player,
+    ) -> impl Stream<Item = (Play, EstimationResult)> {
+        let shared = self.shared.clone();
+        stream::iter(self.max_depth.min(1)..=self.max_depth).then(move |depth| {
+            shared
+                .search(*board, player, depth)
+                .map(move |(column, value)| (Play::try_from((column, player)).unwrap(), value))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::pin,
+        time::{Duration, Instant},
+    };
+
+    use futures::future::{Either, select};
+
+    use super::*;
+    use crate::{
+        Game, SyncEvaluator, best_in_time,
+        executor::{block_on, sleep},
+    };
+
+    #[test]
+    fn same_play_as_min_max() {
+        let evaluator = AsyncMinMax::new(4).with_spawn_depth(2).with_max_tasks(3);
+        let min_max = MinMaxPolicy::new(4);
+        let mut game = Game::default();
+        for c in [4, 3, 4] {
+            let (p, e) = block_on(evaluator.evaluate_game(&game));
+            let (q, f) = min_max.evaluate_game(&game);
+            assert_eq!((p.column(), f64::from(e)), (q.column(), f64::from(f)));
+            game.play(c).unwrap();
+        }
+        assert_eq!(evaluator.shared.permits.available(), 3);
+    }
+
+    #[tokio::test]
+    async fn streams_the_deeper_results_on_tokio() {
+        let evaluator = AsyncMinMax::new(4).with_spawn_depth(2);
+        let game = Game::default();
+        let results: Vec<_> = evaluator.evaluate_game_stream(&game).collect().await;
+        let values: Vec<_> = results.iter().map(|r| f64::from(r.1)).collect();
+        let expected: Vec<_> = (1..=4)
+            .map(|depth| f64::from(MinMaxPolicy::new(depth).evaluate_game(&game).1))
+            .collect();
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn a_dropped_evaluation_gives_back_its_permits() {
+        let evaluator = AsyncMinMax::new(9)
+            .with_spawn_depth(2)
+            .with_max_tasks(3)
+            .with_pool(Arc::new(ThreadPool::new(1)));
+        let game = Game::default();
+        let start = Instant::now();
+        // The evaluation is dropped at the end of the block
+        let timed_out = block_on(async {
+            let evaluation = pin!(evaluator.evaluate_game(&game));
+            let timeout = sleep(Duration::from_millis(20));
+            matches!(select(evaluation, timeout).await, Either::Right(_))
+        });
+        assert!(timed_out);
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert_eq!(evaluator.shared.permits.available(), 3);
+    }
+
+    #[test]
+    fn the_deepest_result_in_time() {
+        let evaluator = AsyncMinMax::new(12).with_spawn_depth(2).with_max_tasks(3);
+        let game = Game::default();
+        let start = Instant::now();
+        let results = evaluator.evaluate_game_stream(&game);
+        let best = block_on(best_in_time(results, Duration::from_millis(100)));
+        assert!(best.is_some());
+        assert!(start.elapsed() < Duration::from_millis(500));
+        // The search still running was dropped with the stream
+        assert_eq!(evaluator.shared.permits.available(), 3);
+    }
+}
@@ -6,31 +6,49 @@ use once_cell::sync::Lazy;
 
 use crate::{Play, Player, game::board::Board};
 
-use crate::thread_pool::ThreadPool;
+use crate::thread_pool::{ThreadPool, ThreadPoolBuilder};
 
 use super::{AsyncEvaluator, EstimationResult, SyncEvaluator};
 
 /// A wrapper around a [`SyncEvaluator`] to make it [`AsyncEvaluator`] by launching async task
 ///
 /// We could use either out [`crate::BlockingFuture`] or [`tokio::task::spawn_blocking`]
+///
+/// The evaluations run on the given pool, or else on a pool shared by all evaluators.
 #[derive(Debug, Clone)]
 pub struct BlockingTaskWrapper<T: SyncEvaluator> {
     evaluator: Arc<T>,
+    pool: Option<Arc<ThreadPool>>,
 }
 
-// Static ThreadPool shared by all evaluators (new)
-static POOL: Lazy<ThreadPool> = Lazy::new(|| ThreadPool::new(4));
-
+// Static ThreadPool shared by all evaluators (new), using the whole machine
+pub(crate) static POOL: Lazy<ThreadPool> = Lazy::new(|| {
+    ThreadPoolBuilder::new()
+        .name("evaluator")
+        .build()
+        .expect("Failed to spawn the shared thread pool")
+});
 
 impl<T: SyncEvaluator> From<T> for BlockingTaskWrapper<T> {
     fn from(value: T) -> Self {
         Self {
             evaluator: value.into(),
+            pool: None,
+        }
+    }
+}
+
+impl<T: SyncEvaluator> BlockingTaskWrapper<T> {
+    /// Run the evaluations of `evaluator` on `pool`
+    pub fn with_pool(evaluator: T, pool: Arc<ThreadPool>) -> Self {
+        Self {
+            evaluator: evaluator.into(),
+            pool: Some(pool),
         }
     }
 }
 
-impl<T: SyncEvaluator + Send + Sync + Clone + 'static> AsyncEvaluator for BlockingTaskWrapper<T> {
+impl<T: SyncEvaluator + Send + Sync + 'static> AsyncEvaluator for BlockingTaskWrapper<T> {
     async fn evaluate(
         &self,
         board: std::sync::Arc<Board>,
@@ -51,11 +69,13 @@ impl<T: SyncEvaluator + Send + Sync + Clone + 'static> AsyncEvaluator for Blocki
                 //     None => (idx, evaluator.evaluate(&b, player).1),
                 // })
 
-                POOL.execute(move || match e {
-                    Some(e) => (idx, EstimationResult::Full(e)),
-                    None => (idx, evaluator.evaluate(&b, player).1),
-                })
-
+                self.pool
+                    .as_deref()
+                    .unwrap_or(&POOL)
+                    .execute(move || match e {
+                        Some(e) => (idx, EstimationResult::Full(e)),
+                        None => (idx, evaluator.evaluate(&b, player).1),
+                    })
             })
             .collect();
 
@@ -63,7 +83,8 @@ impl<T: SyncEvaluator + Send + Sync + Clone + 'static> AsyncEvaluator for Blocki
             .await
             .into_iter()
             .map(|res| {
-                let (idx, est) = res; //.expect("Thread panicked"); //remove expect because ThreadPool handles panic safety internally (new)
+                // A panic of the evaluator is given back to the caller, like with a thread
+                let (idx, est) = res.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
                 (Play::try_from((idx, player)).expect("Invalid move"), est)
             })
             .collect();
@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use futures::future::join_all;
+
+use crate::{Play, Player, game::board::Board, thread_pool::JoinError};
+
+use super::{
+    AsyncEvaluator, EstimationResult, SharedEvaluator, SyncEvaluator, async_wrapper::POOL,
+};
+
+/// An evaluator letting several evaluators vote for the play
+///
+/// All the members evaluate the position in parallel. A member proving a win is followed, otherwise
+/// the play with the most (weighted) votes is chosen, ties broken by the best estimation of the voters.
+///
+/// With [`AsyncEvaluator`], dropping the evaluation before it resolves cancels the members not started yet.
+#[derive(Clone, Default)]
+pub struct VotingEnsemble {
+    members: Vec<(SharedEvaluator, f64)>,
+}
+
+impl VotingEnsemble {
+    /// Create an ensemble without members
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a member whose vote count for `weight`
+    pub fn with_member<T: SyncEvaluator + Send + Sync + 'static>(
+        mut self,
+        evaluator: T,
+        weight: f64,
+    ) -> Self {
+        self.members.push((Arc::new(evaluator), weight));
+        self
+    }
+
+    /// Aggregate the `(play, estimation)` of each member into the elected one
+    fn elect(
+        &self,
+        votes: &[(Play, EstimationResult)],
+        player: Player,
+    ) -> (Play, EstimationResult) {
+        let best = EstimationResult::best_for(votes, player);
+        if best.1 == EstimationResult::Full(crate::End::Win { player }) {
+            return best;
+        }
+        let mut ballot: Vec<(Play, f64, EstimationResult)> = Vec::new();
+        for ((play, estimation), (_, weight)) in votes.iter().zip(self.members.iter()) {
+            match ballot.iter_mut().find(|b| b.0 == *play) {
+                Some(b) => {
+                    b.1 += weight;
+                    if b.2.is_better(estimation, &player) {
+                        b.2 = *estimation;
+                    }
+                }
+                None => ballot.push((*play, *weight, *estimation)),
+            }
+        }
+        let (play, _, estimation) = ballot
+            .into_iter()
+            .reduce(|a, b| {
+                if b.1 > a.1 || (b.1 == a.1 && a.2.is_better(&b.2, &player)) {
+                    b
+                } else {
+                    a
+                }
+            })
+            .expect("An ensemble needs at least one member");
+        (play, estimation)
+    }
+}
+
+impl SyncEvaluator for VotingEnsemble {
+    fn evaluate(&self, board: &Board, player: Player) -> (Play, EstimationResult) {
+        let votes: Vec<_> = std::thread::scope(|s| {
+            let handles: Vec<_> = self
+                .members
+                .iter()
+                .map(|(m, _)| s.spawn(move || m.evaluate(board, player)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("Thread panicked"))
+                .collect()
+        });
+        self.elect(&votes, player)
+    }
+}
+
+impl AsyncEvaluator for VotingEnsemble {
+    async fn evaluate(&self, board: Arc<Board>, player: Player) -> (Play, EstimationResult) {
+        let votes = join_all(self.members.iter().map(|(m, _)| {
+            let m = m.clone();
+            let board = board.clone();
+            POOL.execute(move || m.evaluate(&board, player))
+        }))
+        .await
+        .into_iter()
+        // A panic of a member is given back to the caller, like with the scoped threads
+        .map(|v| match v {
+            Ok(vote) => vote,
+            Err(JoinError::Panic(payload)) => std::panic::resume_unwind(payload),
+            Err(e @ JoinError::Cancelled) => panic!("A member did not vote : {e}"),
+        })
+        .collect::<Vec<_>>();
+        self.elect(&votes, player)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Game,
+        evaluators::{MinMaxPolicy, RandomPolicy},
+    };
+
+    #[test]
+    fn follows_a_proven_win() {
+        // FIRST can win by playing in the column 3
+        let mut game = Game::default();
+        for c in [0, 8, 1, 8, 2, 7] {
+            game.play(c).unwrap();
+        }
+        let ensemble = VotingEnsemble::new()
+            .with_member(RandomPolicy::from_seed(1), 1.0)
+            .with_member(RandomPolicy::from_seed(2), 1.0)
+            .with_member(MinMaxPolicy::new(1), 0.5);
+        assert_eq!(SyncEvaluator::evaluate_game(&ensemble, &game).0.column(), 3);
+    }
+}
@@ -0,0 +1,180 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::debug;
+
+use crate::{
+    Play, Player,
+    caches::{CacheStats, KnowledgeCache, KnowledgeCacheSharded},
+    game::board::Board,
+    heuristic::{Heuristic, NaiveHeuristic},
+};
+
+use super::{
+    EstimationResult, SyncEvaluator,
+    alpha_beta::{Outcome, Searcher, Window, root_moves},
+    stop,
+};
+
+/// A Lazy SMP parallel search
+///
+/// The main thread runs an iterative deepening alpha-beta search up to the maximal depth, while helper
+/// threads run the same search at staggered depths (every other helper one ply ahead) and with the root
+/// moves in a different order. The threads only communicate through the shared knowledge cache : the
+/// endings proven by the helpers cut the search of the main thread, whose result is returned.
+pub struct LazySmp<C = KnowledgeCacheSharded, H = NaiveHeuristic> {
+    max_depth: usize,
+    helpers: usize,
+    seed: Option<u64>,
+    cache: C,
+    heuristic: H,
+}
+
+impl LazySmp {
+    pub fn new(max_depth: usize) -> Self {
+        Self::with_heuristic(max_depth, NaiveHeuristic)
+    }
+}
+
+impl<C: KnowledgeCache + Default, H: Heuristic> LazySmp<C, H> {
+    /// A search evaluating the leaves with `heuristic`, with one helper per additional core
+    pub fn with_heuristic(max_depth: usize, heuristic: H) -> Self {
+        Self::with_cache(max_depth, C::default(), heuristic)
+    }
+}
+
+impl<C: KnowledgeCache, H> LazySmp<C, H> {
+    /// A search sharing `cache` between its threads, and evaluating the leaves with `heuristic`
+    pub fn with_cache(max_depth: usize, cache: C, heuristic: H) -> Self {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self {
+            max_depth,
+            helpers: threads - 1,
+            seed: None,
+            cache,
+            heuristic,
+        }
+    }
+
+    /// Use `helpers` helper threads besides the main one
+    pub fn with_helpers(self, helpers: usize) -> Self {
+        Self { helpers, ..self }
+    }
+
+    /// Break ties between equally good plays at random, reproducibly from `seed`
+    ///
+    /// The root moves of the main thread are searched in an order drawn from `seed`, the first of the
+    /// equally good ones being played. Without a seed, the center columns are preferred.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self {
+            seed: Some(seed),
+            ..self
+        }
+    }
+
+    pub fn get_knowledge_size(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn get_knowledge_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+}
+
+impl<C: KnowledgeCache, H: Heuristic> LazySmp<C, H> {
+    /// Search the root at `depth`, starting with the `rotation`-th root move
+    fn search_root<S: Fn() -> bool>(
+        &self,
+        searcher: &Searcher<'_, C, H, S>,
+        board: &Board,
+        player: Player,
+        depth: usize,
+        rotation: usize,
+    ) -> Option<Outcome> {
+        let mut moves = root_moves(board, player, self.seed);
+        let n = moves.len();
+        moves.rotate_left(rotation % n);
+        searcher.search_moves(board, player, depth, Window::full(), moves)
+    }
+}
+
+impl<C, H> SyncEvaluator for LazySmp<C, H>
+where
+    C: KnowledgeCache + Sync,
+    H: Heuristic + Sync,
+{
+    fn evaluate(&self, board: &Board, player: Player) -> (Play, EstimationResult) {
+        let stop = AtomicBool::new(false);
+        let outer = stop::current();
+        let best = std::thread::scope(|s| {
+            for helper in 1..=self.helpers {
+                let stop = &stop;
+                let outer = outer.clone();
+                s.spawn(move || {
+                    stop::stoppable(outer, || {
+                        let searcher = Searcher {
+                            cache: &self.cache,
+                            heuristic: &self.heuristic,
+                            stop: || stop.load(Ordering::Relaxed),
+                        };
+                        // The helpers may go one ply deeper than the main thread
+                        for depth in (1 + helper % 2)..=(self.max_depth + 1) {
+                            if self
+                                .search_root(&searcher, board, player, depth, helper)
+                                .is_none()
+                            {
+                                break;
+                            }
+                        }
+                    })
+                });
+            }
+
+            let searcher = Searcher {
+                cache: &self.cache,
+                heuristic: &self.heuristic,
+                stop: || false,
+            };
+            let mut best = None;
+            for depth in 1..=self.max_depth.max(1) {
+                // Only a stopped evaluation stops the main search
+                let Some(outcome) = self.search_root(&searcher, board, player, depth, 0) else {
+                    break;
+                };
+                debug!("Depth {depth} : {outcome:?}");
+                best = Some(outcome);
+                if outcome.proven {
+                    break;
+                }
+            }
+            stop.store(true, Ordering::Relaxed);
+            best
+        });
+        match best {
+            Some(best) => (Play::try_from((best.column, player)).unwrap(), best.value),
+            None => stop::abandoned(board, player),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{End, Game};
+
+    #[test]
+    fn helpers_find_the_win() {
+        let smp = LazySmp::new(5).with_helpers(3);
+        let mut game = Game::default();
+        for c in [0, 8, 1, 8, 2, 7] {
+            game.play(c).unwrap();
+        }
+        let (p, e) = smp.evaluate_game(&game);
+        assert_eq!(p.column(), 3);
+        assert_eq!(
+            e,
+            EstimationResult::Full(End::Win {
+                player: Player::FIRST
+            })
+        );
+    }
+}
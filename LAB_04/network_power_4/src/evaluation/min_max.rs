@@ -1,46 +1,100 @@
-use crate::{Play, Player, WIDTH, game::board::Board};
+use crate::{
+    Play, Player, WIDTH,
+    game::board::Board,
+    heuristic::{Heuristic, NaiveHeuristic},
+};
 
-use super::{EstimationResult, SyncEvaluator};
+use super::{EstimationResult, SyncEvaluator, position_rng, stop::stopped};
 
 #[derive(Debug, Copy, Clone)]
 /// A basic MinMaxing policy, with a fixed depth
 ///
+/// The leaves are evaluated with the [`Heuristic`] `H`, [`NaiveHeuristic`] by default.
+///
 /// Not optimised in any way
 /// We might want to add :
 /// - Caching,
 /// - Multi-threading (see [`crate::evaluators::ThreadedPolicy`])
-pub struct MinMaxPolicy {
+pub struct MinMaxPolicy<H = NaiveHeuristic> {
     max_depth: usize,
+    seed: Option<u64>,
+    heuristic: H,
 }
 
 impl MinMaxPolicy {
     pub fn new(max_depth: usize) -> Self {
-        Self { max_depth }
+        Self::with_heuristic(max_depth, NaiveHeuristic)
     }
 }
-fn max(board: &Board, player: Player, depth: usize) -> (usize, EstimationResult) {
-    if depth == 0 {
-        return (WIDTH / 2, EstimationResult::Partial(board.naive_eval()));
-    };
-    let legal_move = board.legal_moves(player);
 
-    let move_evaluation: Vec<(usize, EstimationResult)> = legal_move
-        .into_iter()
-        .map(|(idx, b, e)| match e {
-            Some(e) => (idx, EstimationResult::Full(e)),
-            None => (idx, max(&b, player.other(), depth - 1).1),
-        })
-        .collect();
-    EstimationResult::best_for(&move_evaluation, player)
+impl<H: Heuristic> MinMaxPolicy<H> {
+    /// A policy evaluating the leaves with `heuristic`
+    pub fn with_heuristic(max_depth: usize, heuristic: H) -> Self {
+        Self {
+            max_depth,
+            seed: None,
+            heuristic,
+        }
+    }
+    /// Break ties between equally good plays at random, reproducibly from `seed`
+    ///
+    /// Without a seed, the last best column is always played.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self {
+            seed: Some(seed),
+            ..self
+        }
+    }
+
+    fn evaluate_moves(
+        &self,
+        board: &Board,
+        player: Player,
+        depth: usize,
+    ) -> Vec<(usize, EstimationResult)> {
+        board
+            .legal_moves(player)
+            .into_iter()
+            .map(|(idx, b, e)| match e {
+                Some(e) => (idx, EstimationResult::Full(e)),
+                // The result is dropped anyway
+                None if stopped() => (idx, EstimationResult::Partial(0.0)),
+                None => (idx, self.max(&b, player.other(), depth - 1).1),
+            })
+            .collect()
+    }
+
+    pub(super) fn max(
+        &self,
+        board: &Board,
+        player: Player,
+        depth: usize,
+    ) -> (usize, EstimationResult) {
+        if depth == 0 {
+            return (
+                WIDTH / 2,
+                EstimationResult::Partial(self.heuristic.eval(board)),
+            );
+        };
+        let move_evaluation = self.evaluate_moves(board, player, depth);
+        EstimationResult::best_for(&move_evaluation, player)
+    }
 }
 
-impl SyncEvaluator for MinMaxPolicy {
+impl<H: Heuristic> SyncEvaluator for MinMaxPolicy<H> {
     fn evaluate(
         &self,
         board: &crate::game::board::Board,
         player: crate::Player,
     ) -> (Play, EstimationResult) {
-        let best = max(board, player, self.max_depth);
+        let best = match self.seed {
+            Some(seed) if self.max_depth > 0 => EstimationResult::best_for_with_rng(
+                &self.evaluate_moves(board, player, self.max_depth),
+                player,
+                &mut position_rng(seed, board),
+            ),
+            _ => self.max(board, player, self.max_depth),
+        };
         (Play::try_from((best.0, player)).unwrap(), best.1)
     }
 }
@@ -1,42 +1,97 @@
 use log::trace;
 
-use crate::{Play, Player, WIDTH, caches::KnowledgeCache, game::board::Board};
+use crate::{
+    Play, Player, WIDTH,
+    caches::{CacheStats, KnowledgeCache},
+    game::board::Board,
+    heuristic::{Heuristic, NaiveHeuristic},
+};
 
-use super::{EstimationResult, SyncEvaluator};
+use super::{EstimationResult, SyncEvaluator, position_rng, stop::stopped};
 
 /// A MinMax evaluator with a cache
-pub struct MinMaxPolicyCached<C: KnowledgeCache> {
+///
+/// The leaves are evaluated with the [`Heuristic`] `H`, [`NaiveHeuristic`] by default.
+pub struct MinMaxPolicyCached<C: KnowledgeCache, H = NaiveHeuristic> {
     max_depth: usize,
     knowledge_cache: C,
+    seed: Option<u64>,
+    heuristic: H,
 }
 
 impl<C: KnowledgeCache + Default> MinMaxPolicyCached<C> {
     pub fn new(max_depth: usize) -> Self {
+        Self::with_heuristic(max_depth, NaiveHeuristic)
+    }
+}
+impl<C: KnowledgeCache + Default, H: Heuristic> MinMaxPolicyCached<C, H> {
+    /// A policy evaluating the leaves with `heuristic`
+    pub fn with_heuristic(max_depth: usize, heuristic: H) -> Self {
+        Self::with_cache(max_depth, C::default(), heuristic)
+    }
+}
+impl<C: KnowledgeCache, H: Heuristic> MinMaxPolicyCached<C, H> {
+    /// A policy storing its knowledge into `knowledge_cache`, and evaluating the leaves with `heuristic`
+    pub fn with_cache(max_depth: usize, knowledge_cache: C, heuristic: H) -> Self {
         Self {
             max_depth,
-            knowledge_cache: C::default(),
+            knowledge_cache,
+            seed: None,
+            heuristic,
         }
     }
-}
-impl<C: KnowledgeCache> MinMaxPolicyCached<C> {
+
+    /// Break ties between equally good plays at random, reproducibly from `seed`
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self {
+            seed: Some(seed),
+            ..self
+        }
+    }
+
     pub fn get_knowledge_size(&self) -> usize {
         self.knowledge_cache.len()
     }
 
+    pub fn get_knowledge_stats(&self) -> CacheStats {
+        self.knowledge_cache.stats()
+    }
+
     fn max(&self, board: &Board, player: Player, depth: usize) -> (usize, EstimationResult) {
         if depth == 0 {
-            return (WIDTH / 2, EstimationResult::Partial(board.naive_eval()));
+            return (
+                WIDTH / 2,
+                EstimationResult::Partial(self.heuristic.eval(board)),
+            );
         };
+        let move_evaluation = self.evaluate_moves(board, player, depth);
+        EstimationResult::best_for(&move_evaluation, player)
+    }
+
+    fn evaluate_moves(
+        &self,
+        board: &Board,
+        player: Player,
+        depth: usize,
+    ) -> Vec<(usize, EstimationResult)> {
         let legal_move = board.legal_moves(player);
+        let unknown: Vec<_> = legal_move
+            .iter()
+            .filter(|(_, _, e)| e.is_none())
+            .map(|(_, b, _)| (*b, player))
+            .collect();
+        let mut known = self.knowledge_cache.lookup_batch(&unknown).into_iter();
 
-        let move_evaluation: Vec<(usize, EstimationResult)> = legal_move
+        legal_move
             .iter()
             .map(|(idx, b, e)| match e {
                 Some(e) => {
                     trace!("Cache hit : {e:?}");
                     (*idx, EstimationResult::Full(*e))
                 }
-                None => match self.knowledge_cache.lookup(*b, player) {
+                None => match known.next().flatten() {
+                    // The result is dropped anyway, and a partial one is never remembered
+                    None if stopped() => (*idx, EstimationResult::Partial(0.0)),
                     None => {
                         let estimation = (*idx, self.max(b, player.other(), depth - 1).1);
                         if let EstimationResult::Full(e) = estimation.1 {
@@ -47,27 +102,35 @@ impl<C: KnowledgeCache> MinMaxPolicyCached<C> {
                     Some(x) => (x.0, EstimationResult::Full(x.1)),
                 },
             })
-            .collect();
-        EstimationResult::best_for(&move_evaluation, player)
+            .collect()
     }
 }
 
-impl<C: KnowledgeCache> SyncEvaluator for MinMaxPolicyCached<C> {
+impl<C: KnowledgeCache, H: Heuristic> SyncEvaluator for MinMaxPolicyCached<C, H> {
     fn evaluate(
         &self,
         board: &crate::game::board::Board,
         player: crate::Player,
     ) -> (Play, EstimationResult) {
-        let best = self.max(board, player, self.max_depth);
+        let best = match self.seed {
+            Some(seed) if self.max_depth > 0 => EstimationResult::best_for_with_rng(
+                &self.evaluate_moves(board, player, self.max_depth),
+                player,
+                &mut position_rng(seed, board),
+            ),
+            _ => self.max(board, player, self.max_depth),
+        };
         (Play::try_from((best.0, player)).unwrap(), best.1)
     }
 }
 
-impl<C: KnowledgeCache + Clone> Clone for MinMaxPolicyCached<C> {
+impl<C: KnowledgeCache + Clone, H: Clone> Clone for MinMaxPolicyCached<C, H> {
     fn clone(&self) -> Self {
         Self {
             max_depth: self.max_depth,
             knowledge_cache: self.knowledge_cache.clone(),
+            seed: self.seed,
+            heuristic: self.heuristic.clone(),
         }
     }
 }
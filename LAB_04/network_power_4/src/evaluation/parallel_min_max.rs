@@ -0,0 +1,382 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use parking_lot::{Condvar, Mutex};
+
+use crate::{
+    End, Play, Player,
+    caches::{CacheStats, KnowledgeCache, KnowledgeCacheSharded},
+    game::board::Board,
+    heuristic::{Heuristic, NaiveHeuristic},
+    thread_pool::ThreadPool,
+};
+
+use super::{
+    EstimationResult, SyncEvaluator,
+    alpha_beta::{Best, Outcome, Searcher, Window, remember, root_moves},
+    async_wrapper::POOL,
+    stop::{self, stopped},
+};
+
+/// A parallel alpha-beta search, splitting the work recursively onto a [`ThreadPool`]
+///
+/// It follows the "young brothers wait" idea : at each node deep enough, the first (eldest) child is
+/// searched alone to get a good alpha-beta window, then the other children are searched in parallel.
+/// The siblings share their window, so a sibling starts with the bounds found by the previous ones,
+/// and a cutoff found by one of them stops the others.
+///
+/// The thread searching a node also searches the children that no worker has started yet, so the
+/// pool never waits on a job stuck in its own queue. Proven endings are shared between all the threads
+/// through the knowledge cache.
+pub struct ParallelMinMax<C = KnowledgeCacheSharded, H = NaiveHeuristic> {
+    max_depth: usize,
+    split_depth: usize,
+    seed: Option<u64>,
+    shared: Arc<Shared<C, H>>,
+    /// Pool the searches split onto, the one shared by the evaluators if `None`
+    pool: Option<Arc<ThreadPool>>,
+}
+
+struct Shared<C, H> {
+    cache: C,
+    heuristic: H,
+}
+
+/// An evaluation, shared by the jobs it splits into
+struct Search<C, H> {
+    shared: Arc<Shared<C, H>>,
+    split_depth: usize,
+    pool: Option<Arc<ThreadPool>>,
+}
+
+/// A node whose children are searched in parallel
+struct SplitPoint {
+    /// Split point of the closest parallel ancestor
+    parent: Option<Arc<SplitPoint>>,
+    /// Best child so far, and the window shared by the siblings
+    state: Mutex<(Best, Window)>,
+    cutoff: AtomicBool,
+    /// A sibling is searched by the first thread claiming it
+    claimed: Vec<AtomicBool>,
+    pending: Mutex<usize>,
+    done: Condvar,
+}
+
+type Siblings = Arc<Vec<(usize, Board, Option<End>)>>;
+
+impl SplitPoint {
+    fn finish_one(&self) {
+        let mut pending = self.pending.lock();
+        *pending -= 1;
+        if *pending == 0 {
+            self.done.notify_all();
+        }
+    }
+
+    fn wait(&self) {
+        let mut pending = self.pending.lock();
+        while *pending > 0 {
+            self.done.wait(&mut pending);
+        }
+    }
+}
+
+/// A cutoff happened in one of the parallel ancestors, or the evaluation is stopped : the result is
+/// useless
+fn aborted(split: &Option<Arc<SplitPoint>>) -> bool {
+    if stopped() {
+        return true;
+    }
+    let mut current = split.as_deref();
+    while let Some(s) = current {
+        if s.cutoff.load(Ordering::Acquire) {
+            return true;
+        }
+        current = s.parent.as_deref();
+    }
+    false
+}
+
+impl ParallelMinMax {
+    pub fn new(max_depth: usize) -> Self {
+        Self::with_heuristic(max_depth, NaiveHeuristic)
+    }
+}
+
+impl<C: KnowledgeCache + Default, H: Heuristic> ParallelMinMax<C, H> {
+    /// A search evaluating the leaves with `heuristic`, using all the cores of the machine
+    pub fn with_heuristic(max_depth: usize, heuristic: H) -> Self {
+        Self::with_cache(max_depth, C::default(), heuristic)
+    }
+}
+
+impl<C: KnowledgeCache, H> ParallelMinMax<C, H> {
+    /// A search sharing `cache` between its threads, and evaluating the leaves with `heuristic`
+    pub fn with_cache(max_depth: usize, cache: C, heuristic: H) -> Self {
+        Self {
+            max_depth,
+            split_depth: 3,
+            seed: None,
+            shared: Arc::new(Shared { cache, heuristic }),
+            pool: None,
+        }
+    }
+
+    /// Only split the nodes with at least `split_depth` plies left to search
+    ///
+    /// The nodes closer to the leaves are too small to be worth a job.
+    pub fn with_split_depth(self, split_depth: usize) -> Self {
+        Self {
+            split_depth: split_depth.max(1),
+            ..self
+        }
+    }
+
+    /// Split the searches onto `pool`, instead of the pool shared by the evaluators
+    pub fn with_pool(self, pool: Arc<ThreadPool>) -> Self {
+        Self {
+            pool: Some(pool),
+            ..self
+        }
+    }
+
+    /// Break ties between equally good plays at random, reproducibly from `seed`
+    ///
+    /// The root moves are searched in an order drawn from `seed`, the first of the equally good ones
+    /// being played. As the young brothers are searched in parallel, the ties between them may still
+    /// be broken by the first one to finish. Without a seed, the center columns are preferred.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self {
+            seed: Some(seed),
+            ..self
+        }
+    }
+
+    pub fn get_knowledge_size(&self) -> usize {
+        self.shared.cache.len()
+    }
+
+    pub fn get_knowledge_stats(&self) -> CacheStats {
+        self.shared.cache.stats()
+    }
+}
+
+impl<C, H> Clone for ParallelMinMax<C, H> {
+    fn clone(&self) -> Self {
+        Self {
+            max_depth: self.max_depth,
+            split_depth: self.split_depth,
+            seed: self.seed,
+            shared: self.shared.clone(),
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+impl<C, H> Search<C, H>
+where
+    C: KnowledgeCache + Send + Sync + 'static,
+    H: Heuristic + Send + Sync + 'static,
+{
+    /// Search `board`, the moves of the root in the order drawn from `seed`
+    fn search(
+        self: &Arc<Self>,
+        board: &Board,
+        player: Player,
+        depth: usize,
+        window: Window,
+        split: &Option<Arc<SplitPoint>>,
+        seed: Option<u64>,
+    ) -> Option<Outcome> {
+        let cache = &self.shared.cache;
+        if depth < self.split_depth {
+            let searcher = Searcher {
+                cache,
+                heuristic: &self.shared.heuristic,
+                stop: || aborted(split),
+            };
+            return searcher.search(board, player, depth, window, seed);
+        }
+        if aborted(split) {
+            return None;
+        }
+        if let Some((column, end)) = cache.lookup(*board, player) {
+            return Some(Outcome {
+                column,
+                value: EstimationResult::Full(end),
+                proven: true,
+            });
+        }
+
+        let mut window = window;
+        let mut best = Best::new(player);
+        let mut moves = root_moves(board, player, seed).into_iter();
+
+        // The eldest brother is searched first, alone
+        let (idx, b, e) = moves.next().expect("The board has an ending");
+        let eldest = match e {
+            Some(e) => Outcome {
+                column: idx,
+                value: EstimationResult::Full(e),
+                proven: true,
+            },
+            None => self.search(&b, player.other(), depth - 1, window, split, None)?,
+        };
+        if best.record(idx, eldest, &mut window) || moves.len() == 0 {
+            return Some(remember(cache, board, player, best.finish()));
+        }
+
+        // Then the young brothers, in parallel
+        let siblings: Siblings = Arc::new(moves.collect());
+        let point = Arc::new(SplitPoint {
+            parent: split.clone(),
+            state: Mutex::new((best, window)),
+            cutoff: AtomicBool::new(false),
+            claimed: siblings.iter().map(|_| AtomicBool::new(false)).collect(),
+            pending: Mutex::new(siblings.len()),
+            done: Condvar::new(),
+        });
+        let stop = stop::current();
+        for i in 0..siblings.len() {
+            let search = self.clone();
+            let point = point.clone();
+            let siblings = siblings.clone();
+            let stop = stop.clone();
+            // The result is given through the split point
+            self.pool
+                .as_deref()
+                .unwrap_or(&POOL)
+                .execute(move || {
+                    stop::stoppable(stop, || {
+                        search.search_sibling(&point, &siblings, i, player, depth);
+                    });
+                })
+                .detach();
+        }
+        for i in 0..siblings.len() {
+            self.search_sibling(&point, &siblings, i, player, depth);
+        }
+        point.wait();
+
+        if aborted(split) {
+            return None;
+        }
+        let (best, _) = *point.state.lock();
+        Some(remember(cache, board, player, best.finish()))
+    }
+
+    fn search_sibling(
+        self: &Arc<Self>,
+        point: &Arc<SplitPoint>,
+        siblings: &Siblings,
+        i: usize,
+        player: Player,
+        depth: usize,
+    ) {
+        if point.claimed[i].swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let (idx, b, e) = siblings[i];
+        let child = match e {
+            Some(e) => Some(Outcome {
+                column: idx,
+                value: EstimationResult::Full(e),
+                proven: true,
+            }),
+            None => {
+                let window = point.state.lock().1;
+                let split = Some(point.clone());
+                self.search(&b, player.other(), depth - 1, window, &split, None)
+            }
+        };
+        if let Some(child) = child {
+            let mut state = point.state.lock();
+            let (best, window) = &mut *state;
+            if best.record(idx, child, window) {
+                point.cutoff.store(true, Ordering::Release);
+            }
+        }
+        point.finish_one();
+    }
+}
+
+impl<C, H> SyncEvaluator for ParallelMinMax<C, H>
+where
+    C: KnowledgeCache + Send + Sync + 'static,
+    H: Heuristic + Send + Sync + 'static,
+{
+    fn evaluate(&self, board: &Board, player: Player) -> (Play, EstimationResult) {
+        let search = Arc::new(Search {
+            shared: self.shared.clone(),
+            split_depth: self.split_depth,
+            pool: self.pool.clone(),
+        });
+        let Some(best) = search.search(
+            board,
+            player,
+            self.max_depth,
+            Window::full(),
+            &None,
+            self.seed,
+        ) else {
+            // Only a stopped evaluation aborts the root
+            return stop::abandoned(board, player);
+        };
+        (Play::try_from((best.column, player)).unwrap(), best.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Game, WIDTH, evaluators::MinMaxPolicy};
+
+    #[test]
+    fn same_value_as_min_max() {
+        let parallel = ParallelMinMax::new(4).with_split_depth(2);
+        let min_max = MinMaxPolicy::new(4);
+        let mut game = Game::default();
+        for c in [4, 3] {
+            let a = SyncEvaluator::evaluate_game(&parallel, &game).1;
+            let b = min_max.evaluate_game(&game).1;
+            assert_eq!(f64::from(a), f64::from(b));
+            game.play(c).unwrap();
+        }
+    }
+
+    #[test]
+    fn the_seed_breaks_the_ties() {
+        let game = Game::default();
+        let expected = f64::from(MinMaxPolicy::new(4).evaluate_game(&game).1);
+        let play = |seed| {
+            let parallel = ParallelMinMax::new(4).with_split_depth(5).with_seed(seed);
+            let (p, e) = parallel.evaluate_game(&game);
+            assert_eq!(f64::from(e), expected);
+            p.column()
+        };
+        let columns: Vec<_> = (0..16).map(play).collect();
+        // The empty board is symmetric, so both halves are played
+        assert!(columns.iter().any(|&c| c < WIDTH / 2));
+        assert!(columns.iter().any(|&c| c > WIDTH / 2));
+        assert_eq!(columns, (0..16).map(play).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn finds_the_win() {
+        let parallel = ParallelMinMax::new(5).with_split_depth(2);
+        let mut game = Game::default();
+        for c in [0, 8, 1, 8, 2, 7] {
+            game.play(c).unwrap();
+        }
+        let (p, e) = parallel.evaluate_game(&game);
+        assert_eq!(p.column(), 3);
+        assert_eq!(
+            e,
+            EstimationResult::Full(End::Win {
+                player: Player::FIRST
+            })
+        );
+    }
+}
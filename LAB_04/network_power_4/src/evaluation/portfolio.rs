@@ -0,0 +1,268 @@
+use std::{
+    panic,
+    sync::{Arc, atomic::AtomicBool, mpsc},
+    time::{Duration, Instant},
+};
+
+use futures::{
+    StreamExt,
+    future::{Either, select},
+    stream::FuturesUnordered,
+};
+
+use crate::{
+    Play, Player,
+    executor::sleep,
+    game::board::Board,
+    thread_pool::{JoinError, ThreadPool},
+};
+
+use super::{
+    AsyncEvaluator, EstimationResult, SharedEvaluator, SyncEvaluator,
+    async_wrapper::POOL,
+    stop::{self, StopOnDrop},
+};
+
+/// An evaluator racing several evaluators against a deadline
+///
+/// The members are given from the strongest to the weakest. They all start at once, and the result of the
+/// strongest member that has finished by the deadline is returned (or as soon as the strongest one finishes).
+/// If none has finished by the deadline, the first one to finish is returned, so a fast fallback
+/// (like [`crate::evaluators::RandomPolicy`]) makes sure a play is always available in time. Used as a
+/// [`SyncEvaluator`], the weakest member runs on the calling thread and the others on the pool. Used as an
+/// [`AsyncEvaluator`], all the members run on the pool, and if they all panic the last panic is resumed.
+///
+/// The members still running when the result is returned are stopped : the searches of the crate give up
+/// at their next node, the other evaluators finish in the background and their result is dropped. The
+/// members not started yet are skipped.
+#[derive(Clone)]
+pub struct Portfolio {
+    members: Vec<SharedEvaluator>,
+    deadline: Duration,
+    /// Pool running the members, the shared one if `None`
+    pool: Option<Arc<ThreadPool>>,
+}
+
+impl Portfolio {
+    /// Create a portfolio without members, answering within `deadline`
+    pub fn new(deadline: Duration) -> Self {
+        Self {
+            members: Vec::new(),
+            deadline,
+            pool: None,
+        }
+    }
+
+    /// Run the members on `pool`
+    pub fn with_pool(self, pool: Arc<ThreadPool>) -> Self {
+        Self {
+            pool: Some(pool),
+            ..self
+        }
+    }
+
+    /// Add a member, weaker than the previous ones
+    pub fn with_member<T: SyncEvaluator + Send + Sync + 'static>(mut self, evaluator: T) -> Self {
+        self.members.push(Arc::new(evaluator));
+        self
+    }
+
+    fn pool(&self) -> &ThreadPool {
+        self.pool.as_deref().unwrap_or(&POOL)
+    }
+}
+
+/// Keep the result of the strongest member
+fn keep_strongest<T>(best: &mut Option<(usize, T)>, rank: usize, result: T) {
+    if best.as_ref().is_none_or(|(r, _)| rank < *r) {
+        *best = Some((rank, result));
+    }
+}
+
+impl SyncEvaluator for Portfolio {
+    fn evaluate(&self, board: &Board, player: Player) -> (Play, EstimationResult) {
+        let deadline = Instant::now() + self.deadline;
+        let (weakest, others) = self
+            .members
+            .split_last()
+            .expect("A portfolio needs at least one member");
+        let stop = StopOnDrop(Arc::new(AtomicBool::new(false)));
+        let (tx, rx) = mpsc::channel();
+        // Dropped on return, which skips the members not started yet
+        let _running: Vec<_> = others
+            .iter()
+            .enumerate()
+            .map(|(rank, member)| {
+                let member = member.clone();
+                let board = *board;
+                let stop = stop.0.clone();
+                let tx = tx.clone();
+                self.pool().execute(move || {
+                    let result = stop::stoppable(Some(stop), || member.evaluate(&board, player));
+                    // The portfolio may already have answered
+                    let _ = tx.send((rank, result));
+                })
+            })
+            .collect();
+        drop(tx);
+
+        let mut best = Some((others.len(), weakest.evaluate(board, player)));
+        while let Ok((rank, result)) =
+            rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            keep_strongest(&mut best, rank, result);
+            if rank == 0 {
+                break;
+            }
+        }
+        best.expect("The weakest member has answered").1
+    }
+}
+
+impl AsyncEvaluator for Portfolio {
+    async fn evaluate(&self, board: Arc<Board>, player: Player) -> (Play, EstimationResult) {
+        let stop = StopOnDrop(Arc::new(AtomicBool::new(false)));
+        let mut running: FuturesUnordered<_> = self
+            .members
+            .iter()
+            .enumerate()
+            .map(|(rank, member)| {
+                let member = member.clone();
+                let board = board.clone();
+                let stop = stop.0.clone();
+                let result = self.pool().execute(move || {
+                    stop::stoppable(Some(stop), || member.evaluate(&board, player))
+                });
+                async move { (rank, result.await) }
+            })
+            .collect();
+        let mut deadline = sleep(self.deadline);
+
+        let mut best = None;
+        let mut failure = None;
+        loop {
+            let received = match best {
+                None => running.next().await,
+                Some(_) => match select(running.next(), &mut deadline).await {
+                    Either::Left((r, _)) => r,
+                    Either::Right(_) => None,
+                },
+            };
+            let Some((rank, result)) = received else {
+                break;
+            };
+            // A member that panicked resolves to an error, the others may still answer
+            let result = match result {
+                Ok(result) => result,
+                Err(e) => {
+                    failure = Some(e);
+                    continue;
+                }
+            };
+            keep_strongest(&mut best, rank, result);
+            if rank == 0 {
+                break;
+            }
+        }
+        // The jobs of the members not started yet are cancelled
+        drop(running);
+        match (best, failure) {
+            (Some((_, result)), _) => result,
+            // All the members panicked, the last panic is given back to the caller
+            (None, Some(JoinError::Panic(payload))) => panic::resume_unwind(payload),
+            (None, Some(e)) => panic!("No member of the portfolio answered : {e}"),
+            (None, None) => panic!("A portfolio needs at least one member"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{Game, evaluators::RandomPolicy};
+
+    /// An evaluator far too slow for the deadline
+    struct Slow;
+
+    impl SyncEvaluator for Slow {
+        fn evaluate(&self, _board: &Board, player: Player) -> (Play, EstimationResult) {
+            std::thread::sleep(Duration::from_secs(2));
+            (
+                Play::try_from((0, player)).unwrap(),
+                EstimationResult::Partial(0.0),
+            )
+        }
+    }
+
+    #[test]
+    fn fallback_before_the_deadline() {
+        let portfolio = Portfolio::new(Duration::from_millis(50))
+            .with_member(Slow)
+            .with_member(RandomPolicy::from_seed(0));
+        let start = Instant::now();
+        SyncEvaluator::evaluate_game(&portfolio, &Game::default());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    /// An evaluator searching until it is stopped, counting its searches
+    #[derive(Clone, Default)]
+    struct Stubborn {
+        started: Arc<AtomicUsize>,
+        ended: Arc<AtomicUsize>,
+    }
+
+    impl SyncEvaluator for Stubborn {
+        fn evaluate(&self, board: &Board, player: Player) -> (Play, EstimationResult) {
+            self.started.fetch_add(1, Ordering::SeqCst);
+            let start = Instant::now();
+            while !stop::stopped() && start.elapsed() < Duration::from_secs(2) {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            self.ended.fetch_add(1, Ordering::SeqCst);
+            RandomPolicy::from_seed(0).evaluate(board, player)
+        }
+    }
+
+    #[test]
+    fn the_overrunning_members_are_stopped() {
+        let stubborn = Stubborn::default();
+        let portfolio = Portfolio::new(Duration::from_millis(20))
+            .with_member(stubborn.clone())
+            .with_member(RandomPolicy::from_seed(0))
+            .with_pool(Arc::new(ThreadPool::new(1)));
+        let mut game = Game::default();
+        // The strong member overruns the deadline on two moves in a row
+        for _ in 0..2 {
+            let start = Instant::now();
+            let (play, _) = SyncEvaluator::evaluate_game(&portfolio, &game);
+            assert!(start.elapsed() < Duration::from_millis(500));
+            game.play(play.column()).unwrap();
+            // Stopped, its worker is free for the next move
+            while stubborn.ended.load(Ordering::SeqCst) < stubborn.started.load(Ordering::SeqCst) {
+                assert!(start.elapsed() < Duration::from_millis(500));
+                std::thread::yield_now();
+            }
+        }
+        assert_eq!(stubborn.started.load(Ordering::SeqCst), 2);
+    }
+
+    /// An evaluator that always panics
+    struct Broken;
+
+    impl SyncEvaluator for Broken {
+        fn evaluate(&self, _board: &Board, _player: Player) -> (Play, EstimationResult) {
+            panic!("broken")
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "broken")]
+    fn a_panic_of_every_member_is_given_back() {
+        let portfolio = Portfolio::new(Duration::from_millis(20))
+            .with_member(Broken)
+            .with_member(Broken);
+        crate::executor::block_on(AsyncEvaluator::evaluate_game(&portfolio, &Game::default()));
+    }
+}
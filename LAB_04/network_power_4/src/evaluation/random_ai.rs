@@ -3,21 +3,41 @@ use std::sync::{Arc, Mutex};
 use super::{EstimationResult, SyncEvaluator};
 use crate::game::board::Board;
 use crate::{Play, Player};
-use log::error;
+use log::{error, info};
 use rand::prelude::*;
-use rand::rngs::ThreadRng;
+use rand::rngs::StdRng;
 
+#[derive(Debug, Clone)]
 /// A evaluator playing at random
+///
+/// The generator is seeded, so the sequence of plays can be replayed with [`RandomPolicy::from_seed`].
+/// Clones share the same generator.
 pub struct RandomPolicy {
-    rand: Arc<Mutex<ThreadRng>>,
+    seed: u64,
+    rand: Arc<Mutex<StdRng>>,
 }
 
-impl Default for RandomPolicy {
-    fn default() -> Self {
+impl RandomPolicy {
+    /// Create a policy whose choices are fully determined by `seed`
+    pub fn from_seed(seed: u64) -> Self {
         RandomPolicy {
-            rand: Arc::from(Mutex::from(rand::rng())),
+            seed,
+            rand: Arc::from(Mutex::from(StdRng::seed_from_u64(seed))),
         }
     }
+    /// The seed used to initialise the generator
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl Default for RandomPolicy {
+    /// Draw a fresh seed, and log it so the game can be replayed
+    fn default() -> Self {
+        let seed = rand::random();
+        info!("RandomPolicy seed : {seed}");
+        Self::from_seed(seed)
+    }
 }
 
 impl SyncEvaluator for RandomPolicy {
@@ -27,7 +47,7 @@ impl SyncEvaluator for RandomPolicy {
             panic!();
         } else {
             let moves = board.legal_moves(player);
-            let c = moves.choose(&mut self.rand.lock().unwrap()).unwrap();
+            let c = moves.choose(&mut *self.rand.lock().unwrap()).unwrap();
             (
                 Play::try_from((c.0, player)).unwrap(),
                 EstimationResult::Partial(0.0),
@@ -35,3 +55,25 @@ impl SyncEvaluator for RandomPolicy {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Game;
+
+    fn random_game(seed: u64) -> Vec<Play> {
+        let policy = RandomPolicy::from_seed(seed);
+        let mut game = Game::default();
+        while game.end().is_none() {
+            let (p, _) = policy.evaluate_game(&game);
+            game.play(p.column()).unwrap();
+        }
+        game.history()
+    }
+
+    #[test]
+    fn same_seed_same_game() {
+        assert_eq!(random_game(7), random_game(7));
+        assert_ne!(random_game(7), random_game(8));
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use futures::{
+    Stream,
+    future::{self, Either},
+    stream,
+};
+use rand::seq::IndexedRandom;
+
+use crate::{Play, Player, game::board::Board};
+
+use super::{AsyncEvaluator, EstimationResult, SyncEvaluator, position_rng};
+
+/// A wrapper playing uniformly at random during the opening, then delegating to the inner evaluator
+///
+/// The random plays only depend on the seed and the position, so a game can be replayed exactly.
+#[derive(Debug, Clone)]
+pub struct RandomOpening<T> {
+    inner: T,
+    plies: usize,
+    seed: u64,
+}
+
+impl<T> RandomOpening<T> {
+    /// Play at random while less than `plies` pawns are on the board
+    pub fn new(inner: T, plies: usize, seed: u64) -> Self {
+        Self { inner, plies, seed }
+    }
+
+    fn opening_play(&self, board: &Board, player: Player) -> Option<(Play, EstimationResult)> {
+        if board.played() >= self.plies || board.end().is_some() {
+            return None;
+        }
+        let moves = board.legal_moves(player);
+        let c = moves.choose(&mut position_rng(self.seed, board))?;
+        Some((
+            Play::try_from((c.0, player)).unwrap(),
+            EstimationResult::Partial(0.0),
+        ))
+    }
+}
+
+impl<T: SyncEvaluator> SyncEvaluator for RandomOpening<T> {
+    fn evaluate(&self, board: &Board, player: Player) -> (Play, EstimationResult) {
+        match self.opening_play(board, player) {
+            Some(r) => r,
+            None => self.inner.evaluate(board, player),
+        }
+    }
+}
+
+impl<T: AsyncEvaluator> AsyncEvaluator for RandomOpening<T> {
+    async fn evaluate(&self, board: Arc<Board>, player: Player) -> (Play, EstimationResult) {
+        match self.opening_play(&board, player) {
+            Some(r) => r,
+            None => self.inner.evaluate(board, player).await,
+        }
+    }
+
+    fn evaluate_stream(
+        &self,
+        board: Arc<Board>,
+        player: Player,
+    ) -> impl Stream<Item = (Play, EstimationResult)> {
+        match self.opening_play(&board, player) {
+            Some(r) => Either::Left(stream::once(future::ready(r))),
+            None => Either::Right(self.inner.evaluate_stream(board, player)),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Cooperative cancellation of the evaluations whose result is no longer awaited
+//!
+//! An evaluation run with [`stoppable`] sees [`stopped`] become `true` once its flag is raised. The
+//! searches check it at each node, and then return at once with a meaningless result, which is
+//! dropped by the caller. The jobs spawned by a search carry the flag along with [`current`].
+
+use std::{
+    cell::RefCell,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::{Play, Player, game::board::Board};
+
+use super::EstimationResult;
+
+thread_local! {
+    /// Flag of the evaluation running on this thread
+    static STOP: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// Restore the flag of the outer evaluation, even if `f` panics
+struct Restore(Option<Arc<AtomicBool>>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        STOP.with(|s| *s.borrow_mut() = self.0.take());
+    }
+}
+
+/// Run `f` on this thread, [`stopped`] returning `true` once `flag` is raised
+pub(crate) fn stoppable<R>(flag: Option<Arc<AtomicBool>>, f: impl FnOnce() -> R) -> R {
+    let _restore = Restore(STOP.with(|s| s.replace(flag)));
+    f()
+}
+
+/// The result of the evaluation running on this thread is no longer awaited
+pub(crate) fn stopped() -> bool {
+    STOP.with(|s| {
+        s.borrow()
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+    })
+}
+
+/// Flag of the evaluation running on this thread, to give to the jobs it spawns
+pub(crate) fn current() -> Option<Arc<AtomicBool>> {
+    STOP.with(|s| s.borrow().clone())
+}
+
+/// Raise a flag when dropped, when the evaluation returns or is itself dropped
+pub(crate) struct StopOnDrop(pub Arc<AtomicBool>);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Result of a stopped evaluation, the first legal play
+pub(crate) fn abandoned(board: &Board, player: Player) -> (Play, EstimationResult) {
+    let (column, _, _) = board.legal_moves(player)[0];
+    (
+        Play::try_from((column, player)).unwrap(),
+        EstimationResult::Partial(0.0),
+    )
+}
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use crate::Play;
 
-use super::{EstimationResult, SyncEvaluator};
+use super::{EstimationResult, SyncEvaluator, async_wrapper::POOL};
 
 /// A wrapper around a `SyncEvaluator` to make it multi-treaded
 #[derive(Debug, Clone)]
@@ -16,7 +16,7 @@ impl<T: SyncEvaluator> From<T> for ThreadedPolicy<T> {
     }
 }
 
-impl<T: SyncEvaluator + Sync + Send + Clone + 'static> SyncEvaluator for ThreadedPolicy<T> {
+impl<T: SyncEvaluator + Sync> SyncEvaluator for ThreadedPolicy<T> {
     fn evaluate(
         &self,
         board: &crate::game::board::Board,
@@ -25,39 +25,34 @@ impl<T: SyncEvaluator + Sync + Send + Clone + 'static> SyncEvaluator for Threade
         // Get all possible column indices
         let columns: Vec<usize> = (0..crate::WIDTH).collect();
 
-        // Create one thread per legal move
-        let handles: Vec<_> = columns
+        // One slot per legal move, filled by its job
+        let mut results: Vec<_> = columns
             .into_iter()
             .filter_map(|col| {
                 // Try to construct a valid play
                 let play = Play::try_from((col, player)).ok()?;
 
-                // Clone the board
-                let board_clone = *board;
-
                 // Try to apply the move to get a valid new board
-                let new_board = match board_clone.apply(play) {
-                    Ok((b, _)) => b,
-                    Err(_) => return None,
-                };
-
-                let eval_clone = self.eval.clone();
-                let opponent = player.other();
-
-                // Spawn a thread to evaluate this move
-                Some(std::thread::spawn(move || {
-                    let (_, score) = eval_clone.evaluate(&new_board, opponent);
-                    (play, -score)
-                }))
+                let (new_board, _) = board.apply(play).ok()?;
+                Some((play, new_board, None))
             })
             .collect();
 
-        // Join all threads and collect the results
-        let mut results = Vec::new();
-        for handle in handles {
-            let result = handle.join().expect("Thread panicked");
-            results.push(result);
-        }
+        // Evaluate each move in a job of the shared pool, borrowing the evaluator
+        let eval = &*self.eval;
+        POOL.scope(|s| {
+            for (_, new_board, score) in &mut results {
+                s.spawn(move || {
+                    let (_, s) = eval.evaluate(new_board, player.other());
+                    *score = Some(-s);
+                });
+            }
+        });
+
+        let results: Vec<_> = results
+            .into_iter()
+            .map(|(play, _, score)| (play, score.expect("The scope waits for its jobs")))
+            .collect();
 
         // Return the best move
         EstimationResult::best_for(&results, player)
@@ -0,0 +1,363 @@
+//! A small single-threaded executor, to run the async evaluators without tokio
+//!
+//! [`block_on`] drives a future to completion on the current thread, along with the tasks given to
+//! [`spawn`] meanwhile. The thread sleeps while no task is woken. The [`sleep`] futures are woken by a
+//! timer thread, so they work with any executor.
+use std::{
+    cell::RefCell,
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::{Pin, pin},
+    sync::{
+        Arc, Condvar, Mutex, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use futures::FutureExt;
+use once_cell::sync::Lazy;
+
+use crate::thread_pool::JoinError;
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// The tasks of a [`block_on`] call, ready to be polled
+struct RunQueue {
+    ready: Mutex<VecDeque<Arc<Task>>>,
+    /// Signaled when a task or the main future is woken
+    wake: Condvar,
+    main_woken: AtomicBool,
+    /// All the tasks not over, to drop them at the end of `block_on`
+    tasks: Mutex<Vec<Weak<Task>>>,
+    /// `block_on` returned, the tasks woken later are not queued
+    closed: AtomicBool,
+}
+
+thread_local! {
+    /// Run queue of the innermost `block_on` running on this thread
+    static CURRENT: RefCell<Option<Arc<RunQueue>>> = const { RefCell::new(None) };
+}
+
+struct Task {
+    /// `None` once over
+    future: Mutex<Option<BoxFuture>>,
+    queue: Arc<RunQueue>,
+    /// Already in the run queue, so it is not pushed twice
+    queued: AtomicBool,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        if !self.queued.swap(true, Ordering::AcqRel) && !self.queue.closed.load(Ordering::Acquire) {
+            let queue = self.queue.clone();
+            queue.ready.lock().unwrap().push_back(self);
+            queue.wake.notify_one();
+        }
+    }
+}
+
+/// Waker of the future given to [`block_on`]
+struct MainWaker(Arc<RunQueue>);
+
+impl Wake for MainWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.main_woken.store(true, Ordering::Release);
+        // Notified under the lock, so `block_on` can't miss it between its check and its wait
+        let _ready = self.0.ready.lock().unwrap();
+        self.0.wake.notify_one();
+    }
+}
+
+/// Restore the run queue of the outer `block_on`, even on a panic
+struct Enter(Option<Arc<RunQueue>>);
+
+impl Drop for Enter {
+    fn drop(&mut self) {
+        CURRENT.set(self.0.take());
+    }
+}
+
+/// Run `future` to completion on the current thread, along with the tasks it spawns
+///
+/// The spawned tasks still running when `future` completes are dropped, and their [`JoinHandle`]
+/// resolve to [`JoinError::Cancelled`].
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let queue = Arc::new(RunQueue {
+        ready: Mutex::default(),
+        wake: Condvar::new(),
+        main_woken: AtomicBool::new(true),
+        tasks: Mutex::default(),
+        closed: AtomicBool::new(false),
+    });
+    let _enter = Enter(CURRENT.replace(Some(queue.clone())));
+    let waker = Waker::from(Arc::new(MainWaker(queue.clone())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+
+    loop {
+        if queue.main_woken.swap(false, Ordering::AcqRel)
+            && let Poll::Ready(output) = future.as_mut().poll(&mut cx)
+        {
+            queue.closed.store(true, Ordering::Release);
+            queue.ready.lock().unwrap().clear();
+            let tasks = std::mem::take(&mut *queue.tasks.lock().unwrap());
+            for task in tasks.iter().filter_map(Weak::upgrade) {
+                // Cancels the handle of the task
+                drop(task.future.lock().unwrap().take());
+            }
+            return output;
+        }
+        loop {
+            // Popped out of the lock, as polling may wake tasks
+            let Some(task) = queue.ready.lock().unwrap().pop_front() else {
+                break;
+            };
+            task.queued.store(false, Ordering::Release);
+            let waker = Waker::from(task.clone());
+            let mut slot = task.future.lock().unwrap();
+            if let Some(f) = slot.as_mut()
+                && f.as_mut().poll(&mut Context::from_waker(&waker)).is_ready()
+            {
+                *slot = None;
+            }
+        }
+        let mut ready = queue.ready.lock().unwrap();
+        while ready.is_empty() && !queue.main_woken.load(Ordering::Acquire) {
+            ready = queue.wake.wait(ready).unwrap();
+        }
+    }
+}
+
+/// Run `future` as a task of the current [`block_on`]
+///
+/// Dropping the [`JoinHandle`] does not stop the task.
+///
+/// # Panics
+///
+/// If called outside of [`block_on`].
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let queue = CURRENT
+        .with_borrow(Clone::clone)
+        .expect("spawn must be called from block_on");
+    let state = Arc::new(Mutex::new(JoinState {
+        result: None,
+        waker: None,
+    }));
+    let completion = Completion(state.clone());
+    let task = Arc::new(Task {
+        future: Mutex::new(Some(Box::pin(async move {
+            // A panic is given to the handle, instead of unwinding through the executor
+            let output = AssertUnwindSafe(future).catch_unwind().await;
+            completion.complete(output.map_err(JoinError::Panic));
+        }))),
+        queue,
+        queued: AtomicBool::new(false),
+    });
+    let mut tasks = task.queue.tasks.lock().unwrap();
+    tasks.retain(|t| t.strong_count() > 0);
+    tasks.push(Arc::downgrade(&task));
+    drop(tasks);
+    task.wake();
+    JoinHandle { state }
+}
+
+/// The result of a task once over, or else the waker of the last poll of its handle
+struct JoinState<T> {
+    result: Option<Result<T, JoinError>>,
+    waker: Option<Waker>,
+}
+
+/// The sending side of a [`JoinHandle`], cancelling it if the task is dropped before its end
+struct Completion<T>(Arc<Mutex<JoinState<T>>>);
+
+impl<T> Completion<T> {
+    fn complete(&self, output: Result<T, JoinError>) {
+        let waker = {
+            let mut state = self.0.lock().unwrap();
+            state.result.get_or_insert(output);
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        self.complete(Err(JoinError::Cancelled));
+    }
+}
+
+/// The result of a task given to [`spawn`]
+pub struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// A waker to call at a deadline
+struct Timeout {
+    deadline: Instant,
+    waker: Waker,
+}
+
+impl PartialEq for Timeout {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for Timeout {}
+
+impl PartialOrd for Timeout {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timeout {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.deadline.cmp(&other.deadline)
+    }
+}
+
+/// The timeouts of all the [`Sleep`] futures, the earliest first
+struct Timer {
+    timeouts: Mutex<BinaryHeap<Reverse<Timeout>>>,
+    /// Signaled when an earlier timeout is added
+    changed: Condvar,
+}
+
+static TIMER: Lazy<Arc<Timer>> = Lazy::new(|| {
+    let timer = Arc::new(Timer {
+        timeouts: Mutex::default(),
+        changed: Condvar::new(),
+    });
+    let thread_timer = timer.clone();
+    thread::Builder::new()
+        .name(String::from("timer"))
+        .spawn(move || thread_timer.run())
+        .expect("Failed to spawn the timer thread");
+    timer
+});
+
+impl Timer {
+    fn add(&self, deadline: Instant, waker: Waker) {
+        let mut timeouts = self.timeouts.lock().unwrap();
+        let earliest = timeouts.peek().is_none_or(|t| deadline < t.0.deadline);
+        timeouts.push(Reverse(Timeout { deadline, waker }));
+        if earliest {
+            self.changed.notify_one();
+        }
+    }
+
+    /// Wake the timeouts as they expire, forever
+    fn run(&self) {
+        let mut timeouts = self.timeouts.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            match timeouts.peek() {
+                Some(Reverse(t)) if t.deadline <= now => {
+                    let Reverse(t) = timeouts.pop().unwrap();
+                    t.waker.wake();
+                }
+                Some(Reverse(t)) => {
+                    let timeout = t.deadline - now;
+                    timeouts = self.changed.wait_timeout(timeouts, timeout).unwrap().0;
+                }
+                None => timeouts = self.changed.wait(timeouts).unwrap(),
+            }
+        }
+    }
+}
+
+/// A future completing at a deadline, see [`sleep`]
+#[derive(Debug)]
+pub struct Sleep {
+    deadline: Instant,
+    /// Waker given to the timer by the last poll
+    registered: Option<Waker>,
+}
+
+/// Wait for `duration`
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+/// Wait until `deadline`
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        registered: None,
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        // The timer already has the waker, unless the future moved to another task
+        if !self
+            .registered
+            .as_ref()
+            .is_some_and(|w| w.will_wake(cx.waker()))
+        {
+            TIMER.add(self.deadline, cx.waker().clone());
+            self.registered = Some(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tasks_and_timers() {
+        let start = Instant::now();
+        let output = block_on(async {
+            let slow = spawn(async {
+                sleep(Duration::from_millis(20)).await;
+                2
+            });
+            let fast = spawn(async { 1 });
+            let panicked = spawn(async { panic!("boom") });
+            let never = spawn(sleep(Duration::from_secs(60)));
+            drop(never);
+            assert_eq!(
+                panicked.await.unwrap_err().to_string(),
+                "the job panicked : boom"
+            );
+            fast.await.unwrap() + slow.await.unwrap()
+        });
+        assert_eq!(output, 3);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
@@ -16,7 +16,7 @@ const HORIZONTAL_SEPARATOR: &str = "-";
 const CROSS_SEPARATOR: &str = "+";
 const FILLER: &str = " ";
 
-#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize, PartialOrd, Ord, Hash)]
 /// An enum for the players
 pub enum Player {
     /// First player
@@ -19,7 +19,7 @@ fn render_player(p: &Player) -> char {
     }
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, Hash)]
 /// Represent a board state
 pub struct Board {
     inner: [[Option<Player>; WIDTH]; HEIGHT],
@@ -205,6 +205,31 @@ impl Board {
         cnt + 10.0 * self.count_align(3, Player::FIRST) as f64
             - 10.0 * self.count_align(3, Player::SECOND) as f64
     }
+    /// Build a board from its cells, indexed by `(line, column)` like [`Index`]
+    pub(crate) fn from_cells(inner: [[Option<Player>; WIDTH]; HEIGHT]) -> Self {
+        Self { inner }
+    }
+    /// Number of pawns already played on the board.
+    pub fn played(&self) -> usize {
+        self.inner.iter().flatten().filter(|c| c.is_some()).count()
+    }
+    /// A stable 64 bits fingerprint of the board (FNV-1a over the cells).
+    ///
+    /// Unlike [`std::hash::DefaultHasher`], the value does not depend on the Rust version,
+    /// so it can be used to derive reproducible random streams.
+    pub fn fingerprint(&self) -> u64 {
+        let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+        for cell in self.inner.iter().flatten() {
+            let v = match cell {
+                None => 0,
+                Some(Player::FIRST) => 1,
+                Some(Player::SECOND) => 2,
+            };
+            h ^= v;
+            h = h.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        h
+    }
     /// Get a list of the legal column and board state possible from this board state.
     pub fn legal_moves(&self, p: Player) -> Vec<(usize, Board, Option<End>)> {
         let mut v = Vec::new();
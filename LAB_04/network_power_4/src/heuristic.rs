@@ -0,0 +1,39 @@
+//! Static evaluation of positions, used at the leaves of the searches
+
+pub mod ntuple;
+
+use std::sync::Arc;
+
+use crate::game::board::Board;
+
+pub use ntuple::{NTupleError, NTupleNetwork, TdConfig, TrainingReport};
+
+/// A static evaluation of a position
+///
+/// Positive value mean that the `FIRST` player has an advantage, and negative for the `SECOND` player.
+pub trait Heuristic {
+    /// Evaluate the position `board`
+    fn eval(&self, board: &Board) -> f64;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+/// The hand written heuristic, see [`Board::naive_eval`]
+pub struct NaiveHeuristic;
+
+impl Heuristic for NaiveHeuristic {
+    fn eval(&self, board: &Board) -> f64 {
+        board.naive_eval()
+    }
+}
+
+impl<T: Heuristic + ?Sized> Heuristic for &T {
+    fn eval(&self, board: &Board) -> f64 {
+        (**self).eval(board)
+    }
+}
+
+impl<T: Heuristic + ?Sized> Heuristic for Arc<T> {
+    fn eval(&self, board: &Board) -> f64 {
+        (**self).eval(board)
+    }
+}
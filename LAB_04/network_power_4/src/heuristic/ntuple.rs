@@ -0,0 +1,334 @@
+//! A N-tuple network, trained by temporal difference learning on self-play games
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use log::debug;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    End, Game, HEIGHT, POWER, Player, SyncEvaluator, WIDTH,
+    evaluators::{MinMaxPolicy, RandomPolicy},
+    game::board::Board,
+};
+
+use super::Heuristic;
+
+/// Number of states of a cell : empty, `FIRST` or `SECOND`
+const CELL_STATES: usize = 3;
+
+/// A N-tuple network
+///
+/// Each tuple is a list of cells of the board. The state of the cells of a tuple select one weight in its
+/// look up table, and the value of a position is the sum of the selected weights, squashed with `tanh`.
+/// A value close to `1.0` means that `FIRST` is expected to win, close to `-1.0` that `SECOND` is.
+///
+/// The default network uses every alignment of [`POWER`] cells as tuples, with null weights.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NTupleNetwork {
+    height: usize,
+    width: usize,
+    power: usize,
+    /// Cells `(line, column)` of each tuple
+    tuples: Vec<Vec<(usize, usize)>>,
+    /// Look up table of each tuple
+    weights: Vec<Vec<f64>>,
+}
+
+#[derive(Debug)]
+/// Error while loading or saving a network
+pub enum NTupleError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    /// The network was trained for another board
+    Dimensions {
+        height: usize,
+        width: usize,
+        power: usize,
+    },
+    /// The tuple of this index has a cell out of the board, or a look up table of another size
+    Tuple(usize),
+}
+
+impl From<std::io::Error> for NTupleError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for NTupleError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Format(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Parameters of the TD(lambda) training
+pub struct TdConfig {
+    /// Learning rate
+    pub alpha: f64,
+    /// Decay of the eligibility traces
+    pub lambda: f64,
+    /// Probability to play at random instead of the greedy play, to explore
+    pub epsilon: f64,
+    /// Seed of the self-play games
+    pub seed: u64,
+}
+
+impl Default for TdConfig {
+    fn default() -> Self {
+        Self {
+            alpha: 0.01,
+            lambda: 0.7,
+            epsilon: 0.1,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Outcomes of the self-play games of a training session
+pub struct TrainingReport {
+    pub first_wins: usize,
+    pub second_wins: usize,
+    pub stalls: usize,
+}
+
+impl Default for NTupleNetwork {
+    fn default() -> Self {
+        let mut tuples = Vec::new();
+        // Horizontal, vertical, rising and falling alignments
+        for (dl, dc) in [(0, 1), (1, 0), (1, 1), (-1, 1)] {
+            for row in 0..HEIGHT as isize {
+                for column in 0..WIDTH as isize {
+                    let cells: Vec<_> = (0..POWER as isize)
+                        .map(|i| (row + i * dl, column + i * dc))
+                        .collect();
+                    if cells.iter().all(|&(l, c)| {
+                        (0..HEIGHT as isize).contains(&l) && (0..WIDTH as isize).contains(&c)
+                    }) {
+                        tuples.push(
+                            cells
+                                .iter()
+                                .map(|&(l, c)| (l as usize, c as usize))
+                                .collect(),
+                        );
+                    }
+                }
+            }
+        }
+        Self::with_tuples(tuples)
+    }
+}
+
+impl NTupleNetwork {
+    /// Create a network with null weights over the given tuples of cells
+    pub fn with_tuples(tuples: Vec<Vec<(usize, usize)>>) -> Self {
+        let weights = tuples
+            .iter()
+            .map(|t| vec![0.0; CELL_STATES.pow(t.len() as u32)])
+            .collect();
+        Self {
+            height: HEIGHT,
+            width: WIDTH,
+            power: POWER,
+            tuples,
+            weights,
+        }
+    }
+
+    /// Load a network saved with [`NTupleNetwork::save`]
+    ///
+    /// Fails if the network was trained for other board dimensions, or if its tuples don't match their
+    /// look up tables.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, NTupleError> {
+        let network: Self = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if (network.height, network.width, network.power) != (HEIGHT, WIDTH, POWER) {
+            return Err(NTupleError::Dimensions {
+                height: network.height,
+                width: network.width,
+                power: network.power,
+            });
+        }
+        if network.weights.len() != network.tuples.len() {
+            return Err(NTupleError::Tuple(
+                network.tuples.len().min(network.weights.len()),
+            ));
+        }
+        let invalid = network
+            .tuples
+            .iter()
+            .zip(&network.weights)
+            .position(|(t, lut)| {
+                t.iter().any(|&(l, c)| l >= HEIGHT || c >= WIDTH)
+                    || lut.len() != CELL_STATES.pow(t.len() as u32)
+            });
+        match invalid {
+            Some(index) => Err(NTupleError::Tuple(index)),
+            None => Ok(network),
+        }
+    }
+
+    /// Save the network into a file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), NTupleError> {
+        serde_json::to_writer(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    /// Index of the selected weight in the look up table of each tuple
+    fn features<'a>(&'a self, board: &'a Board) -> impl Iterator<Item = usize> + 'a {
+        self.tuples.iter().map(|t| {
+            t.iter().fold(0, |acc, &cell| {
+                acc * CELL_STATES
+                    + match board[cell] {
+                        None => 0,
+                        Some(Player::FIRST) => 1,
+                        Some(Player::SECOND) => 2,
+                    }
+            })
+        })
+    }
+
+    /// Value of the position, in `]-1, 1[`
+    pub fn value(&self, board: &Board) -> f64 {
+        self.features(board)
+            .zip(self.weights.iter())
+            .map(|(idx, lut)| lut[idx])
+            .sum::<f64>()
+            .tanh()
+    }
+
+    /// Move the value of `board` toward `target`
+    fn update(&mut self, board: &Board, target: f64, alpha: f64) {
+        let v = self.value(board);
+        // Derivative of tanh
+        let step = alpha * (target - v) * (1.0 - v * v);
+        let features: Vec<_> = self.features(board).collect();
+        for (lut, idx) in self.weights.iter_mut().zip(features) {
+            lut[idx] += step;
+        }
+    }
+
+    /// Play one game against itself, and learn from it
+    ///
+    /// Plays are greedy on the current network (a [`MinMaxPolicy`] of depth 1), except with a probability
+    /// `epsilon` where a [`RandomPolicy`] is used.
+    fn self_play(&mut self, config: &TdConfig, random: &RandomPolicy, rng: &mut StdRng) -> End {
+        let mut game = Game::default();
+        let mut states = vec![game.board()];
+        let end = loop {
+            let greedy = MinMaxPolicy::with_heuristic(1, &*self).with_seed(rng.random());
+            let (play, _) = if rng.random_bool(config.epsilon) {
+                random.evaluate_game(&game)
+            } else {
+                greedy.evaluate_game(&game)
+            };
+            if let Some(end) = game.play(play.column()).unwrap() {
+                break end;
+            }
+            states.push(game.board());
+        };
+        let outcome = match end {
+            End::Win {
+                player: Player::FIRST,
+            } => 1.0,
+            End::Win {
+                player: Player::SECOND,
+            } => -1.0,
+            End::Stall => 0.0,
+        };
+        // Offline TD(lambda) : the lambda-return of each state is computed backward from the outcome,
+        // with the values of the network before the update.
+        let values: Vec<_> = states.iter().map(|b| self.value(b)).collect();
+        let mut target = outcome;
+        for t in (0..states.len()).rev() {
+            if t + 1 < states.len() {
+                target = (1.0 - config.lambda) * values[t + 1] + config.lambda * target;
+            }
+            self.update(&states[t], target, config.alpha);
+        }
+        end
+    }
+
+    /// Train the network with TD(lambda) on `games` self-play games
+    pub fn train(&mut self, games: usize, config: &TdConfig) -> TrainingReport {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let random = RandomPolicy::from_seed(rng.random());
+        let mut report = TrainingReport::default();
+        for n in 0..games {
+            match self.self_play(config, &random, &mut rng) {
+                End::Win {
+                    player: Player::FIRST,
+                } => report.first_wins += 1,
+                End::Win {
+                    player: Player::SECOND,
+                } => report.second_wins += 1,
+                End::Stall => report.stalls += 1,
+            }
+            debug!("Self-play game {n} : {report:?}");
+        }
+        report
+    }
+}
+
+impl Heuristic for NTupleNetwork {
+    fn eval(&self, board: &Board) -> f64 {
+        self.value(board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn training_is_reproducible_and_saved() {
+        let config = TdConfig {
+            seed: 3,
+            ..Default::default()
+        };
+        let mut a = NTupleNetwork::default();
+        let mut b = NTupleNetwork::default();
+        assert_eq!(a.train(20, &config), b.train(20, &config));
+        assert_eq!(a, b);
+        assert_ne!(a, NTupleNetwork::default());
+
+        let path = std::env::temp_dir().join(format!("ntuple-{}.json", std::process::id()));
+        a.save(&path).unwrap();
+        let loaded = NTupleNetwork::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for (w, l) in a
+            .weights
+            .iter()
+            .flatten()
+            .zip(loaded.weights.iter().flatten())
+        {
+            assert!((w - l).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn rejects_a_network_out_of_the_board() {
+        let path = std::env::temp_dir().join(format!("ntuple-bad-{}.json", std::process::id()));
+        let mut network = NTupleNetwork::default();
+        network.tuples[2][1] = (HEIGHT, 0);
+        network.save(&path).unwrap();
+        assert!(matches!(
+            NTupleNetwork::load(&path),
+            Err(NTupleError::Tuple(2))
+        ));
+
+        let mut network = NTupleNetwork::default();
+        network.weights[3].pop();
+        network.save(&path).unwrap();
+        assert!(matches!(
+            NTupleNetwork::load(&path),
+            Err(NTupleError::Tuple(3))
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,8 +1,13 @@
 mod blocking_future;
+pub mod blocking_queue;
 pub mod caches;
 mod evaluation;
+pub mod executor;
 mod game;
+pub mod heuristic;
 mod network;
+pub mod registry;
+mod sync;
 mod utils;
 
 /// Height of the game board
@@ -14,7 +19,10 @@ pub const WIDTH: usize = 9;
 pub const POWER: usize = 4;
 
 pub use blocking_future::BlockingFuture;
-pub use evaluation::{AsyncEvaluator, EstimationResult, SyncEvaluator};
+pub use evaluation::{
+    AsyncEvaluator, BoxedAsyncEvaluator, BoxedSyncEvaluator, DynAsyncEvaluator, EstimationResult,
+    SyncEvaluator, best_in_time,
+};
 pub use game::play::Play;
 pub use game::{End, Game, Player};
 pub use network::{AIType, RemoteGame, Roles};
@@ -25,10 +33,11 @@ pub use utils::{
 /// Package off all the robot players
 pub mod evaluators {
     pub use crate::evaluation::{
-        async_wrapper::BlockingTaskWrapper, min_max::MinMaxPolicy,
-        min_max_cached::MinMaxPolicyCached, random_ai::RandomPolicy,
-        threaded_wrapper::ThreadedPolicy,
+        async_min_max::AsyncMinMax, async_wrapper::BlockingTaskWrapper, ensemble::VotingEnsemble,
+        lazy_smp::LazySmp, min_max::MinMaxPolicy, min_max_cached::MinMaxPolicyCached,
+        parallel_min_max::ParallelMinMax, portfolio::Portfolio, random_ai::RandomPolicy,
+        random_opening::RandomOpening, threaded_wrapper::ThreadedPolicy,
     };
 }
 
-mod thread_pool;
+pub mod thread_pool;
@@ -10,6 +10,7 @@ use tokio::{
 use crate::{
     Game, Play,
     game::{End, GamePlayError},
+    registry::Spec,
 };
 
 /// Role in a game
@@ -41,6 +42,20 @@ pub enum AIType {
     LongT,
 }
 
+impl AIType {
+    /// The spec of the evaluator, see [`crate::registry`]
+    pub fn spec(self) -> Spec {
+        let spec = match self {
+            Self::Random => "random",
+            Self::Short => "minmax:depth=4",
+            Self::Long => "minmax:depth=8,cache=sharded",
+            Self::ShortT => "parallel:depth=4",
+            Self::LongT => "parallel:depth=8",
+        };
+        spec.parse().expect("The presets are valid specs")
+    }
+}
+
 /// Handle the network logic
 pub struct RemoteGame {
     /// Local state of the game
@@ -0,0 +1,570 @@
+//! Build the evaluators from a name and parameters, such as `minmax:depth=6,cache=multi`
+//!
+//! A [`Spec`] is the name of an evaluator, optionally followed by a colon and comma separated
+//! `key=value` parameters. The [`Registry`] maps the names to the functions building the evaluators,
+//! and comes with the evaluators of the crate :
+//!
+//! - `random`, with `seed`
+//! - `minmax`, with `depth`, `seed` and `cache`
+//! - `parallel`, the parallel alpha-beta, with `depth`, `split`, `seed` and `cache`
+//! - `lazysmp`, with `depth`, `helpers`, `seed` and `cache`
+//! - `async_minmax`, asynchronous only, with `depth`, `spawn` and `tasks`
+//!
+//! The `cache` is one of `multi`, `sharded`, `bounded` (with `cache_mb`) or `remote` (with
+//! `cache_server`), or `none`. Every synchronous evaluator is also an asynchronous one, its searches
+//! running on the pool of the [`Context`], like the threads of `parallel` and `lazysmp`.
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::{self, Display},
+    str::FromStr,
+    sync::Arc,
+};
+
+use crate::{
+    BoxedAsyncEvaluator, BoxedSyncEvaluator, End, Player,
+    caches::{
+        CacheFileError, CacheStats, Entry, KnowledgeCache, KnowledgeCacheBounded,
+        KnowledgeCacheMultiThread, KnowledgeCacheRemote, KnowledgeCacheSharded,
+    },
+    evaluators::{
+        AsyncMinMax, BlockingTaskWrapper, LazySmp, MinMaxPolicy, MinMaxPolicyCached,
+        ParallelMinMax, RandomPolicy,
+    },
+    game::board::Board,
+    heuristic::{Heuristic, NaiveHeuristic},
+    thread_pool::ThreadPool,
+};
+
+/// The name and the parameters of an evaluator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spec {
+    name: String,
+    params: BTreeMap<String, String>,
+}
+
+impl Spec {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Take the parameter `key` out of the spec, parsed
+    pub fn take<T: FromStr>(&mut self, key: &str) -> Result<Option<T>, SpecError> {
+        match self.params.remove(key) {
+            Some(value) => match value.parse() {
+                Ok(parsed) => Ok(Some(parsed)),
+                Err(_) => Err(SpecError::InvalidValue {
+                    parameter: key.to_string(),
+                    value,
+                }),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Take the parameter `key` out of the spec, which must have it
+    pub fn require<T: FromStr>(&mut self, key: &str) -> Result<T, SpecError> {
+        self.take(key)?.ok_or_else(|| SpecError::MissingParameter {
+            evaluator: self.name.clone(),
+            parameter: key.to_string(),
+        })
+    }
+
+    /// Check that all the parameters were taken
+    fn finish(&self) -> Result<(), SpecError> {
+        match self.params.keys().next() {
+            Some(parameter) => Err(SpecError::UnknownParameter {
+                evaluator: self.name.clone(),
+                parameter: parameter.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FromStr for Spec {
+    type Err = SpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, params) = s.split_once(':').unwrap_or((s, ""));
+        if name.is_empty() {
+            return Err(SpecError::Syntax(s.to_string()));
+        }
+        let params = params
+            .split(',')
+            .filter(|p| !p.is_empty())
+            .map(|p| match p.split_once('=') {
+                Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+                _ => Err(SpecError::Syntax(s.to_string())),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            name: name.to_string(),
+            params,
+        })
+    }
+}
+
+impl Display for Spec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for (i, (key, value)) in self.params.iter().enumerate() {
+            write!(f, "{}{key}={value}", if i == 0 { ':' } else { ',' })?;
+        }
+        Ok(())
+    }
+}
+
+/// A spec that can't be built
+#[derive(Debug)]
+pub enum SpecError {
+    /// The spec is not `name:key=value,...`
+    Syntax(String),
+    UnknownEvaluator(String),
+    /// The evaluator can only be built as an asynchronous one
+    AsyncOnly(String),
+    UnknownParameter {
+        evaluator: String,
+        parameter: String,
+    },
+    MissingParameter {
+        evaluator: String,
+        parameter: String,
+    },
+    InvalidValue {
+        parameter: String,
+        value: String,
+    },
+    /// The cache server can't be reached
+    Cache(CacheFileError),
+}
+
+impl Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax(spec) => write!(f, "`{spec}` is not `name:key=value,...`"),
+            Self::UnknownEvaluator(name) => write!(f, "no evaluator is named `{name}`"),
+            Self::AsyncOnly(name) => {
+                write!(f, "`{name}` is only available as an asynchronous evaluator")
+            }
+            Self::UnknownParameter {
+                evaluator,
+                parameter,
+            } => write!(f, "`{evaluator}` has no parameter `{parameter}`"),
+            Self::MissingParameter {
+                evaluator,
+                parameter,
+            } => write!(f, "`{evaluator}` needs the parameter `{parameter}`"),
+            Self::InvalidValue { parameter, value } => {
+                write!(f, "`{value}` is not a valid `{parameter}`")
+            }
+            Self::Cache(e) => write!(f, "the cache server can't be reached : {e}"),
+        }
+    }
+}
+
+impl Error for SpecError {}
+
+/// A heuristic chosen at runtime
+pub type SharedHeuristic = Arc<dyn Heuristic + Send + Sync>;
+
+/// What the evaluators are built with, besides their parameters
+#[derive(Clone)]
+pub struct Context {
+    /// Evaluation of the leaves of the searches
+    pub heuristic: SharedHeuristic,
+    /// Seed of the random choices, unless the spec has one
+    pub seed: u64,
+    /// Pool running the synchronous evaluators built as asynchronous ones and the leaf searches of
+    /// `async_minmax`, a shared one if `None`
+    ///
+    /// The parallel searches also split onto it, or match its number of threads.
+    pub pool: Option<Arc<ThreadPool>>,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self {
+            heuristic: Arc::new(NaiveHeuristic),
+            seed: 0,
+            pool: None,
+        }
+    }
+}
+
+/// The cache of an evaluator, chosen at runtime
+///
+/// The clones share their entries.
+#[derive(Clone)]
+pub enum SharedCache {
+    Multi(KnowledgeCacheMultiThread),
+    Sharded(KnowledgeCacheSharded),
+    Bounded(KnowledgeCacheBounded),
+    Remote(KnowledgeCacheRemote),
+}
+
+impl SharedCache {
+    /// The cache asked by the `cache` parameter of `spec`, or else `default`
+    fn from_spec(spec: &mut Spec, default: &str) -> Result<Option<Self>, SpecError> {
+        let kind = spec.take::<String>("cache")?;
+        let cache = match kind.as_deref().unwrap_or(default) {
+            "none" => None,
+            "multi" => Some(Self::Multi(KnowledgeCacheMultiThread::default())),
+            "sharded" => Some(Self::Sharded(KnowledgeCacheSharded::default())),
+            "bounded" => {
+                let mib: usize = spec.require("cache_mb")?;
+                Some(Self::Bounded(KnowledgeCacheBounded::with_memory(mib << 20)))
+            }
+            "remote" => {
+                let addr: String = spec.require("cache_server")?;
+                let remote =
+                    KnowledgeCacheRemote::connect(addr.as_str()).map_err(SpecError::Cache)?;
+                Some(Self::Remote(remote))
+            }
+            other => {
+                return Err(SpecError::InvalidValue {
+                    parameter: String::from("cache"),
+                    value: other.to_string(),
+                });
+            }
+        };
+        Ok(cache)
+    }
+
+    fn inner(&self) -> &(dyn KnowledgeCache + Send + Sync) {
+        match self {
+            Self::Multi(c) => c,
+            Self::Sharded(c) => c,
+            Self::Bounded(c) => c,
+            Self::Remote(c) => c,
+        }
+    }
+}
+
+impl KnowledgeCache for SharedCache {
+    fn lookup(&self, board_state: Board, player: Player) -> Option<(usize, End)> {
+        self.inner().lookup(board_state, player)
+    }
+
+    fn lookup_batch(&self, keys: &[(Board, Player)]) -> Vec<Option<(usize, End)>> {
+        self.inner().lookup_batch(keys)
+    }
+
+    fn remember(&self, board_state: Board, player: Player, best_choice: usize, end: End) {
+        self.inner().remember(board_state, player, best_choice, end)
+    }
+
+    fn restore(&self, entries: &[Entry]) {
+        self.inner().restore(entries)
+    }
+
+    fn clean(&mut self) {
+        match self {
+            Self::Multi(c) => c.clean(),
+            Self::Sharded(c) => c.clean(),
+            Self::Bounded(c) => c.clean(),
+            Self::Remote(c) => c.clean(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.inner().len()
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        self.inner().capacity()
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.inner().stats()
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        self.inner().entries()
+    }
+}
+
+/// An evaluator built from a spec, with its cache if it has one
+pub struct Built<E> {
+    pub evaluator: E,
+    /// Shares its entries with the cache of the evaluator, to save or inspect it
+    pub cache: Option<SharedCache>,
+}
+
+impl<E> Built<E> {
+    fn new(evaluator: E) -> Self {
+        Self {
+            evaluator,
+            cache: None,
+        }
+    }
+}
+
+/// Build a synchronous evaluator from its spec, whose parameters it takes
+pub type SyncBuilder = fn(&mut Spec, &Context) -> Result<Built<BoxedSyncEvaluator>, SpecError>;
+
+/// Build an asynchronous evaluator from its spec, whose parameters it takes
+pub type AsyncBuilder = fn(&mut Spec, &Context) -> Result<Built<BoxedAsyncEvaluator>, SpecError>;
+
+/// The evaluators that can be built by name
+pub struct Registry {
+    sync: BTreeMap<String, SyncBuilder>,
+    asynchronous: BTreeMap<String, AsyncBuilder>,
+}
+
+impl Default for Registry {
+    /// The evaluators of the crate
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register_sync("random", random);
+        registry.register_sync("minmax", minmax);
+        registry.register_sync("parallel", parallel);
+        registry.register_sync("lazysmp", lazy_smp);
+        registry.register_async("async_minmax", async_minmax);
+        registry
+    }
+}
+
+impl Registry {
+    /// A registry without any evaluator
+    pub fn empty() -> Self {
+        Self {
+            sync: BTreeMap::new(),
+            asynchronous: BTreeMap::new(),
+        }
+    }
+
+    /// Build the synchronous evaluator `name` with `builder`, replacing the previous one
+    pub fn register_sync(&mut self, name: &str, builder: SyncBuilder) {
+        self.sync.insert(name.to_string(), builder);
+    }
+
+    /// Build the asynchronous evaluator `name` with `builder`, replacing the previous one
+    ///
+    /// It takes over a synchronous evaluator of the same name, when built as an asynchronous one.
+    pub fn register_async(&mut self, name: &str, builder: AsyncBuilder) {
+        self.asynchronous.insert(name.to_string(), builder);
+    }
+
+    /// The evaluator `name` can be built as a synchronous one
+    pub fn has_sync(&self, name: &str) -> bool {
+        self.sync.contains_key(name)
+    }
+
+    /// Names of all the evaluators
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self
+            .sync
+            .keys()
+            .chain(self.asynchronous.keys())
+            .map(String::as_str)
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Build the synchronous evaluator of `spec`
+    pub fn build_sync(
+        &self,
+        spec: &Spec,
+        context: &Context,
+    ) -> Result<Built<BoxedSyncEvaluator>, SpecError> {
+        let builder = self.sync.get(spec.name()).ok_or_else(|| {
+            let name = spec.name().to_string();
+            if self.asynchronous.contains_key(&name) {
+                SpecError::AsyncOnly(name)
+            } else {
+                SpecError::UnknownEvaluator(name)
+            }
+        })?;
+        let mut spec = spec.clone();
+        let built = builder(&mut spec, context)?;
+        spec.finish()?;
+        Ok(built)
+    }
+
+    /// Build the asynchronous evaluator of `spec`, or else wrap its synchronous one
+    pub fn build_async(
+        &self,
+        spec: &Spec,
+        context: &Context,
+    ) -> Result<Built<BoxedAsyncEvaluator>, SpecError> {
+        let Some(builder) = self.asynchronous.get(spec.name()) else {
+            let Built { evaluator, cache } = self.build_sync(spec, context)?;
+            let evaluator: BoxedAsyncEvaluator = match &context.pool {
+                Some(pool) => Box::new(BlockingTaskWrapper::with_pool(evaluator, pool.clone())),
+                None => Box::new(BlockingTaskWrapper::from(evaluator)),
+            };
+            return Ok(Built { evaluator, cache });
+        };
+        let mut spec = spec.clone();
+        let built = builder(&mut spec, context)?;
+        spec.finish()?;
+        Ok(built)
+    }
+}
+
+fn random(spec: &mut Spec, context: &Context) -> Result<Built<BoxedSyncEvaluator>, SpecError> {
+    let seed = spec.take("seed")?.unwrap_or(context.seed);
+    Ok(Built::new(Box::new(RandomPolicy::from_seed(seed))))
+}
+
+fn minmax(spec: &mut Spec, context: &Context) -> Result<Built<BoxedSyncEvaluator>, SpecError> {
+    let depth = spec.require("depth")?;
+    let seed = spec.take("seed")?.unwrap_or(context.seed);
+    let heuristic = context.heuristic.clone();
+    Ok(match SharedCache::from_spec(spec, "none")? {
+        Some(cache) => Built {
+            evaluator: Box::new(
+                MinMaxPolicyCached::with_cache(depth, cache.clone(), heuristic).with_seed(seed),
+            ),
+            cache: Some(cache),
+        },
+        None => Built::new(Box::new(
+            MinMaxPolicy::with_heuristic(depth, heuristic).with_seed(seed),
+        )),
+    })
+}
+
+/// The cache of the parallel searches, shared by their threads
+fn parallel_cache(spec: &mut Spec) -> Result<SharedCache, SpecError> {
+    SharedCache::from_spec(spec, "sharded")?.ok_or_else(|| SpecError::InvalidValue {
+        parameter: String::from("cache"),
+        value: String::from("none"),
+    })
+}
+
+fn parallel(spec: &mut Spec, context: &Context) -> Result<Built<BoxedSyncEvaluator>, SpecError> {
+    let depth = spec.require("depth")?;
+    let split = spec.take("split")?;
+    let seed = spec.take("seed")?.unwrap_or(context.seed);
+    let cache = parallel_cache(spec)?;
+    let mut search =
+        ParallelMinMax::with_cache(depth, cache.clone(), context.heuristic.clone()).with_seed(seed);
+    if let Some(pool) = &context.pool {
+        search = search.with_pool(pool.clone());
+    }
+    if let Some(split) = split {
+        search = search.with_split_depth(split);
+    }
+    Ok(Built {
+        evaluator: Box::new(search),
+        cache: Some(cache),
+    })
+}
+
+fn lazy_smp(spec: &mut Spec, context: &Context) -> Result<Built<BoxedSyncEvaluator>, SpecError> {
+    let depth = spec.require("depth")?;
+    let helpers = spec.take("helpers")?;
+    let seed = spec.take("seed")?.unwrap_or(context.seed);
+    let cache = parallel_cache(spec)?;
+    let mut search =
+        LazySmp::with_cache(depth, cache.clone(), context.heuristic.clone()).with_seed(seed);
+    // As many threads as the pool, unless the spec says otherwise
+    if let Some(pool) = &context.pool {
+        search = search.with_helpers(pool.threads() - 1);
+    }
+    if let Some(helpers) = helpers {
+        search = search.with_helpers(helpers);
+    }
+    Ok(Built {
+        evaluator: Box::new(search),
+        cache: Some(cache),
+    })
+}
+
+fn async_minmax(
+    spec: &mut Spec,
+    context: &Context,
+) -> Result<Built<BoxedAsyncEvaluator>, SpecError> {
+    let depth = spec.require("depth")?;
+    let mut search = AsyncMinMax::with_heuristic(depth, context.heuristic.clone());
+    if let Some(spawn) = spec.take("spawn")? {
+        search = search.with_spawn_depth(spawn);
+    }
+    if let Some(tasks) = spec.take("tasks")? {
+        search = search.with_max_tasks(tasks);
+    }
+    if let Some(pool) = &context.pool {
+        search = search.with_pool(pool.clone());
+    }
+    Ok(Built::new(Box::new(search)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AsyncEvaluator, Game, SyncEvaluator, executor::block_on};
+
+    #[test]
+    fn builds_from_the_spec() {
+        let spec: Spec = "minmax:depth=4,cache=multi".parse().unwrap();
+        assert_eq!(spec.to_string(), "minmax:cache=multi,depth=4");
+
+        let registry = Registry::default();
+        let context = Context::default();
+        let built = registry.build_sync(&spec, &context).unwrap();
+        let game = Game::default();
+        let expected = MinMaxPolicy::new(4).with_seed(0).evaluate_game(&game);
+        assert_eq!(built.evaluator.evaluate_game(&game).1, expected.1);
+        assert!(built.cache.is_some());
+
+        let wrapped = registry.build_async(&spec, &context).unwrap();
+        let expected =
+            block_on(BlockingTaskWrapper::from(MinMaxPolicy::new(4)).evaluate_game(&game));
+        assert_eq!(
+            block_on(wrapped.evaluator.evaluate_game(&game)).1,
+            expected.1
+        );
+
+        // The parallel search splits onto the pool of the context, even a single worker
+        let single = Context {
+            pool: Some(Arc::new(ThreadPool::new(1))),
+            ..Context::default()
+        };
+        let parallel = registry
+            .build_async(&"parallel:depth=4".parse().unwrap(), &single)
+            .unwrap();
+        assert_eq!(
+            block_on(parallel.evaluator.evaluate_game(&game)).1,
+            expected.1
+        );
+
+        // The parallel searches break their ties with the seed of the spec
+        let min_max = MinMaxPolicy::new(4).evaluate_game(&game);
+        for spec in [
+            "parallel:depth=4,seed=3",
+            "lazysmp:depth=4,helpers=0,seed=3",
+        ] {
+            let built = registry
+                .build_sync(&spec.parse().unwrap(), &context)
+                .unwrap();
+            assert_eq!(built.evaluator.evaluate_game(&game).1, min_max.1);
+        }
+
+        let error = |spec: &str| match spec.parse::<Spec>() {
+            Ok(spec) => registry
+                .build_sync(&spec, &context)
+                .err()
+                .unwrap()
+                .to_string(),
+            Err(e) => e.to_string(),
+        };
+        assert_eq!(
+            error("minmax:depth"),
+            "`minmax:depth` is not `name:key=value,...`"
+        );
+        assert_eq!(error("alphago"), "no evaluator is named `alphago`");
+        assert_eq!(error("minmax"), "`minmax` needs the parameter `depth`");
+        assert_eq!(
+            error("minmax:depth=4,width=2"),
+            "`minmax` has no parameter `width`"
+        );
+        assert_eq!(error("random:seed=x"), "`x` is not a valid `seed`");
+        assert_eq!(
+            error("async_minmax:depth=4"),
+            "`async_minmax` is only available as an asynchronous evaluator"
+        );
+    }
+}
@@ -0,0 +1,21 @@
+//! The synchronisation primitives of the thread pool and of the shared caches
+//!
+//! They come from [`loom`] when compiled with `--cfg power4_loom`, so the loom models can explore
+//! every interleaving of their threads. The usual `--cfg loom` is not used, as it also changes tokio :
+//! `RUSTFLAGS="--cfg power4_loom" cargo test --release --lib loom`
+#[cfg(power4_loom)]
+pub(crate) use loom::{
+    sync::{
+        Arc, Condvar, Mutex, RwLock,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    thread, thread_local,
+};
+#[cfg(not(power4_loom))]
+pub(crate) use std::{
+    sync::{
+        Arc, Condvar, Mutex, RwLock,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    thread, thread_local,
+};
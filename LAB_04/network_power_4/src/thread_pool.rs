@@ -1,101 +1,1174 @@
-use std::future::Future;
-use std::sync::{Arc, Mutex, Condvar};
+//! A work-stealing thread pool, running the blocking evaluations for the async code
+use std::any::Any;
+use std::cell::{Cell, RefCell};
 use std::collections::VecDeque;
-use std::thread;
-use std::task::{Context, Poll, Waker};
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
 use std::pin::Pin;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+
+use crate::blocking_queue::{BlockingQueue, CondBlockingQueue, RingQueue, SemBlockingQueue};
+use crate::sync::{Arc, Condvar, Mutex, thread, thread_local};
+use crate::sync::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 
+mod metrics;
+
+use metrics::Recorder;
+pub use metrics::{Histogram, PoolMetrics};
+
+/// A fixed number of worker threads running jobs, with work stealing
+///
+/// Each worker has its own deque of jobs, and the pool has a global injector :
+/// - a job submitted from a worker of the pool is pushed onto the deque of this worker, which runs the
+///   most recent jobs of its deque first (depth first, like a recursive search)
+/// - a job submitted from another thread is pushed onto the injector
+/// - a worker without local jobs takes the oldest job of the injector, or else steals the oldest job of
+///   another worker, or else parks until a job is submitted
+///
+/// The queued jobs of a higher [`Priority`] are always taken first. A job whose future is dropped before
+/// it starts is skipped, see [`ThreadPoolFuture`].
+///
+/// The injector is unbounded by default. With [`ThreadPoolBuilder::bounded_queue`], submitting a job from
+/// another thread blocks while the injector is full.
+///
+/// Dropping the pool shuts it down, after running the jobs already queued.
 pub struct ThreadPool {
-    #[allow(dead_code)] // workers not used yet, but to features like join we will use it
-    workers: Vec<thread::JoinHandle<()>>,
-    queue: Arc<JobQueue>,
+    workers: Mutex<Vec<thread::JoinHandle<()>>>,
+    shared: Arc<Shared>,
 }
 
-struct JobQueue {
-    queue: Mutex<VecDeque<Job>>,
-    condvar: Condvar,
+/// What to do with the queued jobs when the pool shuts down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shutdown {
+    /// Run them before stopping the workers
+    Drain,
+    /// Drop them, the futures of the discarded jobs resolve to [`JoinError::Cancelled`]
+    Discard,
 }
 
-type Job = Box<dyn FnOnce() + Send + 'static>;
+/// Priority of a job
+///
+/// A job submitted by a job of the pool inherits its priority, or else gets [`Priority::Normal`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Speculative work, like pondering during the turn of the opponent
+    Background,
+    #[default]
+    Normal,
+    /// Time-critical work, like the evaluation of the current move
+    Urgent,
+}
 
-impl ThreadPool {
-    pub fn new(n: usize) -> Self {
-        let queue = Arc::new(JobQueue {
-            queue: Mutex::new(VecDeque::new()),
-            condvar: Condvar::new(),
-        });
+impl Priority {
+    /// From the most to the least urgent
+    const ORDER: [Self; 3] = [Self::Urgent, Self::Normal, Self::Background];
+}
+
+/// A job waiting in a queue
+struct Queued {
+    job: Job,
+    since: Instant,
+}
+
+/// A queue for each priority
+type Deques = [VecDeque<Queued>; Priority::ORDER.len()];
+
+/// Flavour of a bounded injector, see [`ThreadPoolBuilder::bounded_queue`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueFlavour {
+    /// A [`CondBlockingQueue`]
+    Condvar,
+    /// A [`SemBlockingQueue`]
+    Semaphore,
+    /// A lock-free [`RingQueue`], whose capacity is rounded up to a power of two
+    LockFree,
+}
+
+/// Queue of the jobs submitted from outside the pool
+enum Injector {
+    /// Unbounded, with a deque for each priority
+    Deques(Mutex<Deques>),
+    /// Bounded, with a queue for each priority
+    Bounded([Box<dyn BlockingQueue<Queued>>; Priority::ORDER.len()]),
+}
+
+impl Injector {
+    fn new(bounded: Option<(QueueFlavour, usize)>) -> Self {
+        let Some((flavour, capacity)) = bounded else {
+            return Self::Deques(Mutex::default());
+        };
+        Self::Bounded(std::array::from_fn(|_| -> Box<dyn BlockingQueue<Queued>> {
+            match flavour {
+                QueueFlavour::Condvar => Box::new(CondBlockingQueue::new(capacity)),
+                QueueFlavour::Semaphore => Box::new(SemBlockingQueue::new(capacity)),
+                QueueFlavour::LockFree => Box::new(RingQueue::new(capacity)),
+            }
+        }))
+    }
+
+    /// Queue `job`, waiting for a free slot if bounded
+    fn push(&self, job: Queued, priority: Priority) {
+        match self {
+            Self::Deques(deques) => deques.lock().unwrap()[priority as usize].push_back(job),
+            Self::Bounded(queues) => queues[priority as usize].put(job),
+        }
+    }
+
+    /// Take the oldest job of `priority`
+    fn pop(&self, priority: Priority) -> Option<Queued> {
+        match self {
+            Self::Deques(deques) => deques.lock().unwrap()[priority as usize].pop_front(),
+            Self::Bounded(queues) => queues[priority as usize].try_get(),
+        }
+    }
+
+    /// Take all the jobs
+    fn drain(&self) -> Vec<Queued> {
+        match self {
+            Self::Deques(deques) => std::mem::take(&mut *deques.lock().unwrap())
+                .into_iter()
+                .flatten()
+                .collect(),
+            Self::Bounded(queues) => queues
+                .iter()
+                .flat_map(|queue| std::iter::from_fn(|| queue.try_get()))
+                .collect(),
+        }
+    }
+}
+
+struct Shared {
+    injector: Injector,
+    /// The deques of each worker
+    locals: Vec<Mutex<Deques>>,
+    /// Number of jobs in the injector and the deques
+    queued: AtomicUsize,
+    /// Number of jobs queued or running
+    pending: AtomicUsize,
+    /// Number of parked workers
+    sleeping: AtomicUsize,
+    /// Number of threads running a job
+    busy: AtomicUsize,
+    completed: AtomicU64,
+    queue_wait: Recorder,
+    run_time: Recorder,
+    shutdown: AtomicBool,
+    /// Lock of the parking and of `join`
+    lock: Mutex<()>,
+    /// Signaled when a job is queued or the pool shuts down
+    wake: Condvar,
+    /// Signaled when no job is pending
+    idle: Condvar,
+}
 
-        let mut workers = Vec::with_capacity(n);
-        for _ in 0..n {
-            let queue_clone = Arc::clone(&queue);
-            workers.push(thread::spawn(move || loop {
-                let job = {
-                    let mut q = queue_clone.queue.lock().unwrap();
-                    while q.is_empty() {
-                        q = queue_clone.condvar.wait(q).unwrap();
+/// Return `false` if the job was skipped without running
+type Job = Box<dyn FnOnce() -> bool + Send + 'static>;
+
+// The thread locals of loom can't have a `const` initializer
+thread_local! {
+    /// The pool and the index of the worker running on this thread
+    #[allow(clippy::missing_const_for_thread_local)]
+    static WORKER: RefCell<Option<(Arc<Shared>, usize)>> = RefCell::new(None);
+    /// Priority of the job running on this thread
+    #[allow(clippy::missing_const_for_thread_local)]
+    static PRIORITY: Cell<Priority> = Cell::new(Priority::Normal);
+}
+
+/// Index of the current thread if it is a worker of `shared`
+fn worker_index(shared: &Shared) -> Option<usize> {
+    WORKER.with(|w| match &*w.borrow() {
+        Some((pool, index)) if std::ptr::eq(&**pool, shared) => Some(*index),
+        _ => None,
+    })
+}
+
+/// Priority of a job submitted to `shared` without an explicit one
+fn inherited_priority(shared: &Arc<Shared>) -> Priority {
+    match worker_index(shared) {
+        Some(_) => PRIORITY.with(Cell::get),
+        None => Priority::Normal,
+    }
+}
+
+impl Shared {
+    /// Queue `job`, onto the deque of the current worker if there is one
+    fn push(&self, job: Job, priority: Priority) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        // Counted before being visible, so a worker taking it never sees a negative count
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        let job = Queued {
+            job,
+            since: Instant::now(),
+        };
+        match worker_index(self) {
+            Some(index) => self.locals[index].lock().unwrap()[priority as usize].push_back(job),
+            None => self.injector.push(job, priority),
+        }
+        // Either a parking worker sees the job, or it is counted as sleeping here. The count is read
+        // by a read-modify-write, ordered with the one of the worker without relying on `SeqCst` loads,
+        // which loom models as mere acquires.
+        if self.sleeping.fetch_add(0, Ordering::SeqCst) > 0 {
+            let _lock = self.lock.lock().unwrap();
+            self.wake.notify_one();
+        }
+    }
+
+    /// Take the most urgent job : from the own deque, the injector, or another worker
+    fn find_job(&self, index: usize) -> Option<(Job, Priority)> {
+        let n = self.locals.len();
+        Priority::ORDER.into_iter().find_map(|priority| {
+            let p = priority as usize;
+            // The own deque is unlocked before locking the others, two workers stealing from each
+            // other would deadlock otherwise
+            let own = self.locals[index].lock().unwrap()[p].pop_back();
+            let job = own.or_else(|| self.injector.pop(priority)).or_else(|| {
+                (1..n).find_map(|i| self.locals[(index + i) % n].lock().unwrap()[p].pop_front())
+            })?;
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            self.queue_wait.record(job.since.elapsed());
+            Some((job.job, priority))
+        })
+    }
+
+    /// Wait for a job to be queued, return `false` if the pool is shut down
+    fn park(&self) -> bool {
+        let lock = self.lock.lock().unwrap();
+        self.sleeping.fetch_add(1, Ordering::SeqCst);
+        let shutdown = self.shutdown.load(Ordering::SeqCst);
+        if self.queued.load(Ordering::SeqCst) > 0 {
+            // The job may be counted but not pushed yet, let its submitter run
+            drop(lock);
+            thread::yield_now();
+        } else if !shutdown {
+            drop(self.wake.wait(lock).unwrap());
+        }
+        self.sleeping.fetch_sub(1, Ordering::SeqCst);
+        !shutdown
+    }
+
+    /// Count a job as over, it ran or was discarded
+    fn finish(&self, jobs: usize) {
+        if jobs > 0 && self.pending.fetch_sub(jobs, Ordering::SeqCst) == jobs {
+            let _lock = self.lock.lock().unwrap();
+            self.idle.notify_all();
+        }
+    }
+
+    /// Run a job taken from the queues with its priority
+    fn run(&self, job: Job, priority: Priority) {
+        let outer = PRIORITY.with(|p| p.replace(priority));
+        self.busy.fetch_add(1, Ordering::Relaxed);
+        let start = Instant::now();
+        if job() {
+            self.run_time.record(start.elapsed());
+            self.completed.fetch_add(1, Ordering::Relaxed);
+        }
+        self.busy.fetch_sub(1, Ordering::Relaxed);
+        PRIORITY.with(|p| p.set(outer));
+        self.finish(1);
+    }
+
+    /// Run the jobs until the pool shuts down and the queues are empty
+    fn work(shared: Arc<Self>, index: usize) {
+        WORKER.with(|w| *w.borrow_mut() = Some((shared.clone(), index)));
+        loop {
+            match shared.find_job(index) {
+                Some((job, priority)) => shared.run(job, priority),
+                None => {
+                    if !shared.park() && shared.queued.load(Ordering::SeqCst) == 0 {
+                        break;
                     }
-                    q.pop_front()
-                };
-                if let Some(job) = job {
-                    job();
                 }
-            }));
+            }
+        }
+        WORKER.with(|w| *w.borrow_mut() = None);
+    }
+}
+
+/// Configuration of a [`ThreadPool`]
+#[derive(Debug, Default, Clone)]
+pub struct ThreadPoolBuilder {
+    threads: Option<usize>,
+    name: Option<String>,
+    stack_size: Option<usize>,
+    bounded: Option<(QueueFlavour, usize)>,
+}
+
+impl ThreadPoolBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of workers, the available parallelism of the machine by default
+    ///
+    /// # Panics
+    ///
+    /// If `threads` is zero.
+    pub fn threads(self, threads: usize) -> Self {
+        assert!(threads > 0, "A thread pool needs at least one worker");
+        Self {
+            threads: Some(threads),
+            ..self
+        }
+    }
+
+    /// Name the workers `{name}-{index}`, `pool-{index}` by default
+    pub fn name<S: Into<String>>(self, name: S) -> Self {
+        Self {
+            name: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Stack size of the workers in bytes, the one of [`std::thread`] by default
+    pub fn stack_size(self, bytes: usize) -> Self {
+        Self {
+            stack_size: Some(bytes),
+            ..self
         }
+    }
+
+    /// Bound the injector to `capacity` jobs, with a blocking queue of the given flavour
+    ///
+    /// Submitting a job from outside the pool then waits while the injector is full, so a fast producer
+    /// can't queue more jobs than the workers keep up with. The jobs submitted by the jobs of the pool
+    /// go to the deques of the workers, which are never bounded. Each [`Priority`] has its own queue of
+    /// `capacity` jobs, so the urgent jobs are not stuck behind the background ones.
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is zero.
+    pub fn bounded_queue(self, flavour: QueueFlavour, capacity: usize) -> Self {
+        assert!(capacity > 0, "A bounded queue needs at least one slot");
+        Self {
+            bounded: Some((flavour, capacity)),
+            ..self
+        }
+    }
+
+    /// Spawn the workers
+    pub fn build(self) -> std::io::Result<ThreadPool> {
+        let n = self
+            .threads
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+        let name = self.name.unwrap_or_else(|| String::from("pool"));
+        let shared = Arc::new(Shared {
+            injector: Injector::new(self.bounded),
+            locals: (0..n).map(|_| Mutex::default()).collect(),
+            queued: AtomicUsize::new(0),
+            pending: AtomicUsize::new(0),
+            sleeping: AtomicUsize::new(0),
+            busy: AtomicUsize::new(0),
+            completed: AtomicU64::new(0),
+            queue_wait: Recorder::default(),
+            run_time: Recorder::default(),
+            shutdown: AtomicBool::new(false),
+            lock: Mutex::new(()),
+            wake: Condvar::new(),
+            idle: Condvar::new(),
+        });
+
+        // Dropped on an error, shutting down the workers already spawned
+        let pool = ThreadPool {
+            workers: Mutex::new(Vec::with_capacity(n)),
+            shared,
+        };
+        for index in 0..n {
+            let mut builder = thread::Builder::new().name(format!("{name}-{index}"));
+            if let Some(bytes) = self.stack_size {
+                builder = builder.stack_size(bytes);
+            }
+            let shared_clone = Arc::clone(&pool.shared);
+            let worker = builder.spawn(move || Shared::work(shared_clone, index))?;
+            pool.workers.lock().unwrap().push(worker);
+        }
+        Ok(pool)
+    }
+}
+
+impl ThreadPool {
+    /// A pool of `n` workers, see [`ThreadPoolBuilder`] for more options
+    ///
+    /// # Panics
+    ///
+    /// If `n` is zero, or a worker can't be spawned.
+    pub fn new(n: usize) -> Self {
+        ThreadPoolBuilder::new()
+            .threads(n)
+            .build()
+            .expect("Failed to spawn the workers of the thread pool")
+    }
 
-        Self { workers, queue }
+    /// Number of workers
+    pub fn threads(&self) -> usize {
+        self.shared.locals.len()
     }
 
+    /// Current state of the pool, and counters since its creation
+    ///
+    /// The values are read one by one while the pool runs, so they may be slightly inconsistent.
+    pub fn metrics(&self) -> PoolMetrics {
+        let shared = &self.shared;
+        PoolMetrics {
+            threads: self.threads(),
+            queued: shared.queued.load(Ordering::Relaxed),
+            busy: shared.busy.load(Ordering::Relaxed),
+            idle: shared.sleeping.load(Ordering::Relaxed),
+            completed: shared.completed.load(Ordering::Relaxed),
+            queue_wait: shared.queue_wait.snapshot(),
+            run_time: shared.run_time.snapshot(),
+        }
+    }
+
+    /// Run `f` on a worker
+    ///
+    /// From a job of the pool, `f` is pushed onto the deque of the worker running the job.
+    ///
+    /// # Panics
+    ///
+    /// If the pool is shut down.
     pub fn execute<F, X>(&self, f: F) -> ThreadPoolFuture<X>
     where
         F: FnOnce() -> X + Send + 'static,
         X: Send + 'static,
     {
-        let result = Arc::new(Mutex::new(None));
-        let waker_slot = Arc::new((Mutex::new(None::<Waker>), Condvar::new()));
-        let ready = Arc::new(AtomicBool::new(false));
-
-        let result_clone = result.clone();
-        let waker_clone = waker_slot.clone();
-        let ready_clone = ready.clone();
-
-        let job = Box::new(move || {
-            let output = f();
-            *result_clone.lock().unwrap() = Some(output);
-            ready_clone.store(true, Ordering::SeqCst);
-            if let Some(w) = waker_clone.0.lock().unwrap().take() {
-                w.wake();
+        match self.try_execute(f) {
+            Ok(future) => future,
+            Err(_) => panic!("The thread pool is shut down"),
+        }
+    }
+
+    /// Run `f` on a worker, or give it back if the pool is shut down
+    pub fn try_execute<F, X>(&self, f: F) -> Result<ThreadPoolFuture<X>, F>
+    where
+        F: FnOnce() -> X + Send + 'static,
+        X: Send + 'static,
+    {
+        self.try_execute_with_priority(inherited_priority(&self.shared), f)
+    }
+
+    /// Run `f` on a worker with the given priority
+    ///
+    /// # Panics
+    ///
+    /// If the pool is shut down.
+    pub fn execute_with_priority<F, X>(&self, priority: Priority, f: F) -> ThreadPoolFuture<X>
+    where
+        F: FnOnce() -> X + Send + 'static,
+        X: Send + 'static,
+    {
+        match self.try_execute_with_priority(priority, f) {
+            Ok(future) => future,
+            Err(_) => panic!("The thread pool is shut down"),
+        }
+    }
+
+    /// Run `f` on a worker with the given priority, or give it back if the pool is shut down
+    pub fn try_execute_with_priority<F, X>(
+        &self,
+        priority: Priority,
+        f: F,
+    ) -> Result<ThreadPoolFuture<X>, F>
+    where
+        F: FnOnce() -> X + Send + 'static,
+        X: Send + 'static,
+    {
+        if self.shared.shutdown.load(Ordering::SeqCst) {
+            return Err(f);
+        }
+        let (job, future) = job(f);
+        self.shared.push(job, priority);
+        Ok(future)
+    }
+
+    /// Wait until all the submitted jobs have run
+    ///
+    /// It must not be called from a job of the pool, which would wait for itself.
+    pub fn join(&self) {
+        let mut lock = self.shared.lock.lock().unwrap();
+        while self.shared.pending.load(Ordering::SeqCst) > 0 {
+            lock = self.shared.idle.wait(lock).unwrap();
+        }
+    }
+
+    /// Stop accepting jobs, drain or discard the queues, and join the workers
+    ///
+    /// The jobs already running are always completed. When called from a job of the pool, the worker
+    /// running it stops once the job is over, without being joined.
+    pub fn shutdown(&self, mode: Shutdown) {
+        {
+            let _lock = self.shared.lock.lock().unwrap();
+            self.shared.shutdown.store(true, Ordering::SeqCst);
+            self.shared.wake.notify_all();
+        }
+        if mode == Shutdown::Discard {
+            // The jobs are dropped out of the locks, as they may own anything
+            let mut discarded = self.shared.injector.drain();
+            discarded.extend(
+                self.shared
+                    .locals
+                    .iter()
+                    .flat_map(|q| std::mem::take(&mut *q.lock().unwrap()))
+                    .flatten(),
+            );
+            self.shared
+                .queued
+                .fetch_sub(discarded.len(), Ordering::SeqCst);
+            let n = discarded.len();
+            drop(discarded);
+            self.shared.finish(n);
+        }
+
+        let workers = std::mem::take(&mut *self.workers.lock().unwrap());
+        let current = thread::current().id();
+        for worker in workers {
+            if worker.thread().id() != current {
+                // The jobs never unwind, see `job`
+                let _ = worker.join();
             }
+        }
+    }
+}
+
+/// Run `f` on the pool running the current job, pushing it onto the deque of the current worker
+///
+/// It inherits the priority of the current job.
+///
+/// `f` is given back if the current thread is not a worker, or if its pool is shut down.
+pub fn spawn<F, X>(f: F) -> Result<ThreadPoolFuture<X>, F>
+where
+    F: FnOnce() -> X + Send + 'static,
+    X: Send + 'static,
+{
+    let Some(shared) = WORKER.with(|w| w.borrow().as_ref().map(|(s, _)| s.clone())) else {
+        return Err(f);
+    };
+    if shared.shutdown.load(Ordering::SeqCst) {
+        return Err(f);
+    }
+    let (job, future) = job(f);
+    shared.push(job, PRIORITY.with(Cell::get));
+    Ok(future)
+}
+
+impl ThreadPool {
+    /// Run `f` with a [`Scope`], whose jobs may borrow anything living longer than the call
+    ///
+    /// All the jobs spawned in the scope are over when `scope` returns. If `f` or one of the jobs
+    /// panics, the panic is resumed once all the jobs are over. Called from a job of the pool, the
+    /// worker runs the queued jobs while it waits, so the pool can't be exhausted by waiting scopes.
+    pub fn scope<'env, F, R>(&self, f: F) -> R
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R,
+    {
+        let scope = Scope {
+            pool: self,
+            state: Arc::new(ScopeState {
+                jobs: Mutex::new(ScopeJobs::default()),
+                done: Condvar::new(),
+                panic: Mutex::new(None),
+            }),
+            scope: PhantomData,
+            env: PhantomData,
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+        scope.wait();
+        let panic = scope.state.panic.lock().unwrap().take();
+        match (result, panic) {
+            (Err(payload), _) | (Ok(_), Some(payload)) => panic::resume_unwind(payload),
+            (Ok(result), None) => result,
+        }
+    }
+}
+
+/// A scope to spawn jobs borrowing from the caller, see [`ThreadPool::scope`]
+pub struct Scope<'scope, 'env: 'scope> {
+    pool: &'scope ThreadPool,
+    state: Arc<ScopeState>,
+    /// Invariant lifetimes, like [`std::thread::Scope`]
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+struct ScopeState {
+    jobs: Mutex<ScopeJobs>,
+    /// Signaled when a job is over or discarded
+    done: Condvar,
+    /// First panic of a job
+    panic: Mutex<Option<Box<dyn Any + Send + 'static>>>,
+}
+
+/// The jobs of a scope not over yet
+#[derive(Default)]
+struct ScopeJobs {
+    /// Number of jobs not over, including the discarded ones
+    running: usize,
+    /// Jobs discarded by a shutdown of the pool, which the scope runs on its own thread
+    discarded: Vec<Box<dyn FnOnce() + Send + 'static>>,
+}
+
+/// A job of a scope, counted as over once it ran, or given back to the scope if discarded
+struct ScopeJob {
+    f: Option<Box<dyn FnOnce() + Send + 'static>>,
+    state: Arc<ScopeState>,
+}
+
+impl ScopeJob {
+    fn run(mut self) {
+        let f = self.f.take().expect("A scoped job runs once");
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(f)) {
+            self.state.panic.lock().unwrap().get_or_insert(payload);
+        }
+    }
+}
+
+impl Drop for ScopeJob {
+    fn drop(&mut self) {
+        let mut jobs = self.state.jobs.lock().unwrap();
+        match self.f.take() {
+            Some(f) => jobs.discarded.push(f),
+            None => jobs.running -= 1,
+        }
+        self.state.done.notify_all();
+    }
+}
+
+impl<'scope> Scope<'scope, '_> {
+    /// Run `f` on the pool, before the end of the scope
+    ///
+    /// The jobs of a scope are never cancelled. If the pool is shut down, `f` runs on the current thread,
+    /// and if it is discarded by [`Shutdown::Discard`], on the thread waiting for the scope.
+    pub fn spawn<F>(&'scope self, f: F)
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        self.state.jobs.lock().unwrap().running += 1;
+        let f: Box<dyn FnOnce() + Send + 'scope> = Box::new(f);
+        // SAFETY: the job only borrows data living for `'scope`, and `ThreadPool::scope` does not
+        // return before the job is over : it waits until every job has run, either on the pool or on
+        // its own thread once discarded, and the job is not touched after.
+        let f = unsafe {
+            std::mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Box<dyn FnOnce() + Send>>(f)
+        };
+        let scope_job = ScopeJob {
+            f: Some(f),
+            state: self.state.clone(),
+        };
+        let shared = &self.pool.shared;
+        if shared.shutdown.load(Ordering::SeqCst) {
+            scope_job.run();
+            return;
+        }
+        let job: Job = Box::new(move || {
+            scope_job.run();
+            true
         });
+        shared.push(job, inherited_priority(shared));
+        // The workers may be gone before the job was queued, then it runs here with the others left
+        if shared.shutdown.load(Ordering::SeqCst) {
+            let index = worker_index(shared).unwrap_or(0);
+            while let Some((job, priority)) = shared.find_job(index) {
+                shared.run(job, priority);
+            }
+        }
+    }
 
-        self.queue.queue.lock().unwrap().push_back(job);
-        self.queue.condvar.notify_one();
+    /// Wait for all the jobs of the scope
+    ///
+    /// A worker of the pool runs the queued jobs meanwhile, as does any thread once the pool is shut
+    /// down, since the workers may be gone before the last jobs are queued. The discarded jobs of the
+    /// scope run here.
+    fn wait(&self) {
+        let shared = &self.pool.shared;
+        let worker = worker_index(shared);
+        let mut jobs = self.state.jobs.lock().unwrap();
+        while jobs.running > 0 {
+            if let Some(f) = jobs.discarded.pop() {
+                drop(jobs);
+                ScopeJob {
+                    f: Some(f),
+                    state: self.state.clone(),
+                }
+                .run();
+                jobs = self.state.jobs.lock().unwrap();
+                continue;
+            }
+            let helper = worker.or_else(|| shared.shutdown.load(Ordering::SeqCst).then_some(0));
+            if let Some(index) = helper {
+                drop(jobs);
+                let found = shared.find_job(index);
+                let ran = found.is_some();
+                if let Some((job, priority)) = found {
+                    shared.run(job, priority);
+                }
+                jobs = self.state.jobs.lock().unwrap();
+                if ran {
+                    continue;
+                }
+            }
+            // The jobs left are running on other threads, each one signals its end
+            jobs = self.state.done.wait(jobs).unwrap();
+        }
+    }
+}
+
+/// Wrap `f` into a job, and the future of its result
+fn job<F, X>(f: F) -> (Job, ThreadPoolFuture<X>)
+where
+    F: FnOnce() -> X + Send + 'static,
+    X: Send + 'static,
+{
+    let state = Arc::new(Mutex::new(JobState {
+        result: None,
+        waker: None,
+    }));
+    let cancelled = Arc::new(AtomicBool::new(false));
 
+    let completion = Completion {
+        state: state.clone(),
+        done: false,
+    };
+    let cancelled_clone = cancelled.clone();
+
+    let job = Box::new(move || {
+        // Nobody waits for the result
+        if cancelled_clone.load(Ordering::SeqCst) {
+            return false;
+        }
+        // The worker survives the panic, which is given to the future
+        let output = panic::catch_unwind(AssertUnwindSafe(f)).map_err(JoinError::Panic);
+        completion.complete(output);
+        true
+    });
+
+    (
+        job,
         ThreadPoolFuture {
-            result,
-            ready,
-            waker_slot,
+            state,
+            cancelled,
+            detached: false,
+        },
+    )
+}
+
+impl fmt::Debug for ThreadPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThreadPool")
+            .field("threads", &self.threads())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.shutdown(Shutdown::Drain);
+    }
+}
+
+/// A job that did not give its result
+pub enum JoinError {
+    /// The job panicked, with this payload
+    Panic(Box<dyn Any + Send + 'static>),
+    /// The job was discarded before running, see [`Shutdown::Discard`]
+    Cancelled,
+}
+
+impl JoinError {
+    /// Payload of the panic
+    ///
+    /// # Panics
+    ///
+    /// If the job was cancelled.
+    pub fn into_panic(self) -> Box<dyn Any + Send + 'static> {
+        match self {
+            Self::Panic(payload) => payload,
+            Self::Cancelled => panic!("The job was cancelled, it did not panic"),
+        }
+    }
+
+    /// Message of the panic, when it is a string
+    fn message(&self) -> Option<&str> {
+        match self {
+            Self::Panic(payload) => payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str)),
+            Self::Cancelled => None,
+        }
+    }
+}
+
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Panic(_) => write!(f, "Panic({:?})", self.message()),
+            Self::Cancelled => write!(f, "Cancelled"),
         }
     }
 }
 
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self, self.message()) {
+            (Self::Panic(_), Some(message)) => write!(f, "the job panicked : {message}"),
+            (Self::Panic(_), None) => write!(f, "the job panicked"),
+            (Self::Cancelled, _) => write!(f, "the job was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+/// The result of a job once over, or else the waker of the last poll of its future
+///
+/// Both are behind the same lock, so the job can't complete between the check of the result and the
+/// storage of the waker : a woken future always finds the result.
+struct JobState<T> {
+    result: Option<Result<T, JoinError>>,
+    waker: Option<Waker>,
+}
+
+/// The sending side of a [`ThreadPoolFuture`], cancelling it if the job is dropped without running
+struct Completion<T> {
+    state: Arc<Mutex<JobState<T>>>,
+    done: bool,
+}
+
+impl<T> Completion<T> {
+    fn complete(mut self, output: Result<T, JoinError>) {
+        self.send(output);
+    }
+
+    fn send(&mut self, output: Result<T, JoinError>) {
+        self.done = true;
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            state.result = Some(output);
+            state.waker.take()
+        };
+        // Woken out of the lock, the task may poll at once
+        if let Some(w) = waker {
+            w.wake();
+        }
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.send(Err(JoinError::Cancelled));
+        }
+    }
+}
+
+/// The result of a job of a [`ThreadPool`]
+///
+/// Dropping the future cancels the job if it has not started yet, unless the future is
+/// [detached](ThreadPoolFuture::detach).
 pub struct ThreadPoolFuture<T> {
-    result: Arc<Mutex<Option<T>>>,
-    ready: Arc<AtomicBool>,
-    waker_slot: Arc<(Mutex<Option<Waker>>, Condvar)>,
+    state: Arc<Mutex<JobState<T>>>,
+    cancelled: Arc<AtomicBool>,
+    detached: bool,
+}
+
+impl<T> ThreadPoolFuture<T> {
+    /// Let the job run even if nobody waits for its result
+    pub fn detach(mut self) {
+        self.detached = true;
+    }
+}
+
+impl<T> Drop for ThreadPoolFuture<T> {
+    fn drop(&mut self) {
+        if !self.detached {
+            self.cancelled.store(true, Ordering::SeqCst);
+        }
+    }
 }
 
 impl<T> Future for ThreadPoolFuture<T> {
-    type Output = T;
+    type Output = Result<T, JoinError>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        if self.ready.load(Ordering::SeqCst) {
-            let mut result = self.result.lock().unwrap();
-            Poll::Ready(result.take().unwrap())
-        } else {
-            let mut waker_lock = self.waker_slot.0.lock().unwrap();
-            *waker_lock = Some(cx.waker().clone());
-            Poll::Pending
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(power4_loom)))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn join_and_shutdown() {
+        let pool = ThreadPool::new(2);
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..8 {
+            let done = done.clone();
+            pool.execute(move || {
+                thread::sleep(Duration::from_millis(5));
+                done.fetch_add(1, Ordering::SeqCst);
+            })
+            .detach();
+        }
+        pool.join();
+        assert_eq!(done.load(Ordering::SeqCst), 8);
+
+        // A single worker, busy while the other jobs are discarded
+        let pool = ThreadPool::new(1);
+        let (started, wait) = std::sync::mpsc::channel();
+        pool.execute(move || {
+            started.send(()).unwrap();
+            thread::sleep(Duration::from_millis(20));
+        })
+        .detach();
+        for _ in 0..4 {
+            let done = done.clone();
+            pool.execute(move || done.fetch_add(1, Ordering::SeqCst))
+                .detach();
+        }
+        wait.recv().unwrap();
+        pool.shutdown(Shutdown::Discard);
+        assert_eq!(done.load(Ordering::SeqCst), 8);
+        assert!(pool.try_execute(|| ()).is_err());
+        assert!(pool.workers.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn priorities_and_cancellation() {
+        for bounded in [None, Some(QueueFlavour::Condvar)] {
+            let mut pool = ThreadPoolBuilder::new().threads(1);
+            if let Some(flavour) = bounded {
+                pool = pool.bounded_queue(flavour, 2);
+            }
+            let pool = pool.build().unwrap();
+            let (started, wait) = std::sync::mpsc::channel();
+            let (release, blocked) = std::sync::mpsc::channel::<()>();
+            pool.execute(move || {
+                started.send(()).unwrap();
+                blocked.recv().unwrap();
+            })
+            .detach();
+            wait.recv().unwrap();
+
+            let order = Arc::new(Mutex::new(Vec::new()));
+            let push = |name: &'static str| {
+                let order = order.clone();
+                move || order.lock().unwrap().push(name)
+            };
+            pool.execute_with_priority(Priority::Background, push("background"))
+                .detach();
+            pool.execute(push("normal")).detach();
+            drop(pool.execute_with_priority(Priority::Urgent, push("cancelled")));
+            pool.execute_with_priority(Priority::Urgent, push("urgent"))
+                .detach();
+            release.send(()).unwrap();
+            pool.join();
+            assert_eq!(*order.lock().unwrap(), ["urgent", "normal", "background"]);
+            // The cancelled job did not run
+            assert_eq!(pool.metrics().completed, 4);
+        }
+    }
+
+    #[test]
+    fn jobs_spawn_children() {
+        assert!(spawn(|| ()).is_err());
+        let pool = ThreadPool::new(2);
+        let children = pool.execute(|| {
+            (0..3)
+                .map(|i| spawn(move || i * 10).ok().unwrap())
+                .collect::<Vec<_>>()
+        });
+        let children = crate::executor::block_on(children).unwrap();
+        let results = crate::executor::block_on(futures::future::join_all(children));
+        let values: Vec<_> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(values, [0, 10, 20]);
+    }
+
+    #[test]
+    fn workers_steal_from_each_other() {
+        /// Spawn a binary tree of jobs, `depth` levels deep, counting its leaves
+        fn tree(depth: usize, leaves: Arc<AtomicUsize>) {
+            if depth == 0 {
+                leaves.fetch_add(1, Ordering::SeqCst);
+                return;
+            }
+            for _ in 0..2 {
+                let leaves = leaves.clone();
+                spawn(move || tree(depth - 1, leaves))
+                    .ok()
+                    .unwrap()
+                    .detach();
+            }
+        }
+
+        // A deadlock fails the test instead of hanging it
+        let (done, finished) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            for _ in 0..20 {
+                let pool = ThreadPool::new(4);
+                let leaves = Arc::new(AtomicUsize::new(0));
+                let root = leaves.clone();
+                pool.execute(move || tree(10, root)).detach();
+                pool.join();
+                done.send(leaves.load(Ordering::SeqCst)).unwrap();
+            }
+        });
+        for _ in 0..20 {
+            assert_eq!(finished.recv_timeout(Duration::from_secs(10)), Ok(1 << 10));
         }
     }
+
+    #[test]
+    fn builder_names_the_workers() {
+        let pool = ThreadPoolBuilder::new()
+            .threads(2)
+            .name("test")
+            .stack_size(1 << 20)
+            .build()
+            .unwrap();
+        assert_eq!(pool.threads(), 2);
+        let name = pool.execute(|| thread::current().name().map(String::from));
+        let name = crate::executor::block_on(name).unwrap().unwrap();
+        assert!(name == "test-0" || name == "test-1");
+    }
+
+    #[test]
+    fn a_panic_resolves_the_future() {
+        let pool = ThreadPool::new(1);
+        let panicked = pool.execute(|| -> usize { panic!("boom") });
+        let error = crate::executor::block_on(panicked).unwrap_err();
+        assert_eq!(error.to_string(), "the job panicked : boom");
+        // The worker is still alive
+        assert_eq!(crate::executor::block_on(pool.execute(|| 4)).unwrap(), 4);
+    }
+
+    #[test]
+    fn scoped_jobs_borrow_the_stack() {
+        let pool = ThreadPool::new(2);
+        let mut values = [0usize; 8];
+        let offset = 10;
+        pool.scope(|s| {
+            for (i, chunk) in values.chunks_mut(2).enumerate() {
+                s.spawn(move || {
+                    for v in chunk {
+                        *v = offset + i;
+                    }
+                });
+            }
+        });
+        assert_eq!(values, [10, 10, 11, 11, 12, 12, 13, 13]);
+    }
+
+    #[test]
+    fn discarded_scoped_jobs_run_on_the_scope() {
+        // A single worker, busy while the other jobs are discarded
+        let pool = ThreadPool::new(1);
+        let mut values = [0usize; 4];
+        let (started, wait) = std::sync::mpsc::channel();
+        pool.scope(|s| {
+            let (first, others) = values.split_first_mut().unwrap();
+            s.spawn(move || {
+                started.send(()).unwrap();
+                thread::sleep(Duration::from_millis(20));
+                *first = 1;
+            });
+            for v in others {
+                s.spawn(move || *v = 1);
+            }
+            wait.recv().unwrap();
+            pool.shutdown(Shutdown::Discard);
+        });
+        assert_eq!(values, [1; 4]);
+    }
+
+    #[test]
+    fn metrics_count_the_jobs() {
+        let pool = ThreadPool::new(2);
+        for _ in 0..4 {
+            pool.execute(|| thread::sleep(Duration::from_millis(2)))
+                .detach();
+        }
+        pool.join();
+        let metrics = pool.metrics();
+        assert_eq!((metrics.queued, metrics.busy, metrics.completed), (0, 0, 4));
+        assert_eq!(metrics.queue_wait.count(), 4);
+        assert!(metrics.run_time.quantile(0.0) >= Duration::from_millis(2));
+    }
+
+    #[test]
+    fn bounded_queue_applies_backpressure() {
+        for flavour in [
+            QueueFlavour::Condvar,
+            QueueFlavour::Semaphore,
+            QueueFlavour::LockFree,
+        ] {
+            let pool = ThreadPoolBuilder::new()
+                .threads(1)
+                .bounded_queue(flavour, 2)
+                .build()
+                .unwrap();
+            let done = Arc::new(AtomicUsize::new(0));
+            for _ in 0..8 {
+                let done = done.clone();
+                // Blocks while two jobs wait in the queue
+                pool.execute(move || {
+                    thread::sleep(Duration::from_millis(1));
+                    done.fetch_add(1, Ordering::SeqCst);
+                })
+                .detach();
+                assert!(pool.metrics().queued <= 3);
+            }
+            pool.join();
+            assert_eq!(done.load(Ordering::SeqCst), 8);
+        }
+    }
+}
+
+#[cfg(all(test, power4_loom))]
+mod loom_models {
+    use super::*;
+    use loom::future::block_on;
+
+    #[test]
+    fn a_woken_future_observes_its_result() {
+        loom::model(|| {
+            let (job, future) = job(|| 42);
+            let worker = thread::spawn(job);
+            // A lost wake up would block the model forever, which loom reports
+            assert_eq!(block_on(future).unwrap(), 42);
+            worker.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn a_dropped_job_cancels_its_future() {
+        loom::model(|| {
+            let (job, future) = job(|| 42);
+            let discarder = thread::spawn(move || drop(job));
+            assert!(matches!(block_on(future), Err(JoinError::Cancelled)));
+            discarder.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn a_parked_worker_wakes_up_for_a_job() {
+        loom::model(|| {
+            let pool = ThreadPool::new(1);
+            assert_eq!(block_on(pool.execute(|| 1)).unwrap(), 1);
+            // Shuts down and joins the worker, which must not miss the shutdown either
+            drop(pool);
+        });
+    }
 }
@@ -0,0 +1,152 @@
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Number of buckets of a [`Histogram`], the last one holds the durations over 2^30 µs (about 18 min)
+const BUCKETS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Distribution of durations, in buckets of powers of two of microseconds
+///
+/// The bucket `0` counts the durations under 1 µs, and the bucket `i` the ones in [2^(i-1), 2^i) µs.
+pub struct Histogram {
+    pub buckets: [u64; BUCKETS],
+    /// Sum of the durations
+    pub total: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: [0; BUCKETS],
+            total: Duration::ZERO,
+        }
+    }
+}
+
+impl Histogram {
+    /// Number of recorded durations
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.count() {
+            0 => Duration::ZERO,
+            n => self.total.div_f64(n as f64),
+        }
+    }
+
+    /// Upper bound of the bucket holding the `q` quantile (`q` in [0, 1])
+    pub fn quantile(&self, q: f64) -> Duration {
+        let rank = (q.clamp(0.0, 1.0) * self.count() as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (i, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Duration::from_micros(1 << i);
+            }
+        }
+        Duration::ZERO
+    }
+}
+
+impl Display for Histogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "mean {:?}, p50 < {:?}, p99 < {:?}",
+            self.mean(),
+            self.quantile(0.5),
+            self.quantile(0.99)
+        )
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// State and counters of a [`super::ThreadPool`], see [`super::ThreadPool::metrics`]
+pub struct PoolMetrics {
+    pub threads: usize,
+    /// Jobs waiting in the queues
+    pub queued: usize,
+    /// Threads running a job
+    pub busy: usize,
+    /// Workers parked, waiting for a job
+    pub idle: usize,
+    /// Jobs run since the creation of the pool
+    pub completed: u64,
+    /// Time between the submission of the jobs and their start
+    pub queue_wait: Histogram,
+    /// Time spent running the jobs
+    pub run_time: Histogram,
+}
+
+impl Display for PoolMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} threads ({} busy, {} idle), {} queued, {} completed, queue wait : {}, run time : {}",
+            self.threads,
+            self.busy,
+            self.idle,
+            self.queued,
+            self.completed,
+            self.queue_wait,
+            self.run_time
+        )
+    }
+}
+
+#[derive(Debug)]
+/// The counters behind a [`Histogram`], cheap to update from several threads
+pub(super) struct Recorder {
+    buckets: [AtomicU64; BUCKETS],
+    /// Sum of the durations in nanoseconds
+    total: AtomicU64,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            total: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Recorder {
+    pub fn record(&self, duration: Duration) {
+        let micros = duration.as_micros().min(u64::MAX as u128) as u64;
+        let bucket = (u64::BITS - micros.leading_zeros()) as usize;
+        self.buckets[bucket.min(BUCKETS - 1)].fetch_add(1, Ordering::Relaxed);
+        let nanos = duration.as_nanos().min(u64::MAX as u128) as u64;
+        self.total.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> Histogram {
+        Histogram {
+            buckets: std::array::from_fn(|i| self.buckets[i].load(Ordering::Relaxed)),
+            total: Duration::from_nanos(self.total.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_by_powers_of_two() {
+        let recorder = Recorder::default();
+        for micros in [0, 1, 3, 3, 100] {
+            recorder.record(Duration::from_micros(micros));
+        }
+        let histogram = recorder.snapshot();
+        assert_eq!(histogram.buckets[..8], [1, 1, 2, 0, 0, 0, 0, 1]);
+        assert_eq!(histogram.count(), 5);
+        assert_eq!(histogram.quantile(0.5), Duration::from_micros(4));
+        assert_eq!(histogram.quantile(1.0), Duration::from_micros(128));
+    }
+}
@@ -1,9 +1,12 @@
 use std::time::Duration;
 
-use futures::pin_mut;
-use network_power_4::{RemoteGame, evaluators::RandomPolicy, play_until_end};
+use network_power_4::{RemoteGame, evaluators::RandomPolicy, play_until_end_with_sync};
 use tokio::time::Instant;
 
+/// Fixed seeds, so a failing game can be replayed exactly
+const SERVER_SEED: u64 = 4545;
+const CLIENT_SEED: u64 = 5454;
+
 #[test]
 fn init_remote_game() {
     let rt = tokio::runtime::Builder::new_multi_thread()
@@ -16,12 +19,12 @@ fn init_remote_game() {
         let init_conn = futures::future::join(
             async {
                 let game = RemoteGame::new_server(addr).await;
-                play_until_end(game).await;
+                play_until_end_with_sync(game, RandomPolicy::from_seed(SERVER_SEED)).await;
             },
             async {
                 tokio::time::sleep(Duration::from_millis(100)).await;
                 let client = RemoteGame::new_client(addr).await;
-                play_until_end(client).await
+                play_until_end_with_sync(client, RandomPolicy::from_seed(CLIENT_SEED)).await
             },
         );
 